@@ -0,0 +1,31 @@
+// Steps per second of Simulator::progress as the number of balls grows. The
+// balls are scattered over a pocketless table with random velocities, as set
+// up in common::scattered.
+
+#[macro_use]
+extern crate criterion;
+extern crate justshoot;
+extern crate rand;
+
+mod common;
+
+use criterion::{Criterion, ParameterizedBenchmark, Throughput};
+
+use common::scattered;
+
+fn bench_progress(c: &mut Criterion) {
+    c.bench(
+        "progress",
+        ParameterizedBenchmark::new(
+            "balls",
+            |b, &n_balls| {
+                let mut simulator = scattered(n_balls);
+                b.iter(|| simulator.progress())
+            },
+            vec![1, 16, 100, 400, 1000],
+        ).throughput(|_| Throughput::Elements(1)),
+    );
+}
+
+criterion_group!(benches, bench_progress);
+criterion_main!(benches);
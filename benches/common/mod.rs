@@ -0,0 +1,40 @@
+// Setups shared by the benchmarks.
+
+use rand::{Rng, SeedableRng, prng::XorShiftRng};
+
+use justshoot::geometry::JVector3;
+use justshoot::jlib::{consts, Ball, Simulator, Table, WorldConf};
+
+// n_balls scattered over a pocketless table with random velocities. The
+// collisions are elastic, so the balls keep moving and the density, and with
+// it the number of collisions, stays the same however long the simulator is
+// stepped.
+pub fn scattered(n_balls: usize) -> Simulator {
+    let r = consts::POOL_BALL_RADIUS;
+    let spacing = 4. * r;
+    let per_row = (n_balls as f64).sqrt().ceil() as usize;
+    let side = per_row as f64 * spacing;
+
+    let mut rng = XorShiftRng::from_seed([1; 16]);
+    let balls = (0..n_balls).map(|k| {
+        let mut ball = Ball::at_rest(JVector3::new(
+            ((k % per_row) as f64 + 0.5) * spacing - side / 2.,
+            ((k / per_row) as f64 + 0.5) * spacing - side / 2.,
+            r,
+        ));
+        ball.u = JVector3::new(rng.gen_range(-1., 1.), rng.gen_range(-1., 1.), 0.);
+        ball
+    }).collect();
+
+    let mut world_conf = WorldConf::pool();
+    world_conf.ball_ball_rest = 1.;
+    world_conf.table = Some(Table {
+        length: side,
+        width: side,
+        ball_cushion_rest: 1.,
+        cushion_height: consts::POOL_CUSHION_HEIGHT,
+        pockets: vec![],
+    });
+
+    Simulator::new(balls, world_conf, 1e-4)
+}
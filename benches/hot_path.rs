@@ -0,0 +1,110 @@
+// Timings of the functions every simulation step or every frame goes through,
+// to have numbers before optimizing them and to catch regressions:
+//
+// * the quaternion helpers used to turn the balls and to draw them, next to
+//   the plain quaternion products they replace,
+// * SimulationStateSeq::calc_interpolated_at on long sequences,
+// * complete shots from the golden catalogue, run until everything is at
+//   rest.
+//
+// Simulator::progress itself is timed in broad_phase.
+
+#[macro_use]
+extern crate criterion;
+extern crate justshoot;
+extern crate rand;
+
+mod common;
+
+use criterion::{Criterion, ParameterizedBenchmark, Throughput, black_box};
+
+use justshoot::geometry::{
+    JQuaternion, JUnitQuaternion, JVector3, calc_interpolated_quaternion, rotate_point, rotate_points, rotation_during,
+};
+use justshoot::golden;
+use justshoot::jlib::SimulationStateSeq;
+
+use common::scattered;
+
+fn bench_quaternions(c: &mut Criterion) {
+    let q1 = JUnitQuaternion::from_euler_angles(0.3, -1.2, 2.);
+    let q2 = JUnitQuaternion::from_euler_angles(0.5, -1., 2.4);
+    let v = JVector3::new(0.01, -0.02, 0.0286);
+    c.bench_function("rotate_point", move |b| {
+        b.iter(|| rotate_point(black_box(&v), black_box(&q1)))
+    });
+    // The two quaternion products rotate_point used to be, for comparison.
+    c.bench_function("rotate_point_by_products", move |b| {
+        b.iter(|| {
+            let (v, q) = (black_box(&v), black_box(&q1));
+            let qr = q.quaternion() * JQuaternion::new(0., v.x, v.y, v.z) * q.inverse().quaternion();
+            JVector3::new(qr.as_vector().x, qr.as_vector().y, qr.as_vector().z)
+        })
+    });
+    c.bench(
+        "rotate_points",
+        ParameterizedBenchmark::new(
+            "points",
+            move |b, &n_points| {
+                let points: Vec<JVector3> = (0..n_points).map(|k| v * (k as f64 + 1.)).collect();
+                let mut out = Vec::with_capacity(n_points);
+                b.iter(|| rotate_points(black_box(&points), black_box(&q1), &mut out))
+            },
+            vec![1, 3, 16],
+        ).throughput(|&n_points| Throughput::Elements(n_points as u32)),
+    );
+    let omega = JVector3::new(30., -20., 5.);
+    c.bench_function("rotation_during", move |b| {
+        b.iter(|| rotation_during(black_box(&omega), 1e-4, false))
+    });
+    c.bench_function("rotation_during_portable", move |b| {
+        b.iter(|| rotation_during(black_box(&omega), 1e-4, true))
+    });
+    c.bench_function("calc_interpolated_quaternion", move |b| {
+        b.iter(|| calc_interpolated_quaternion(black_box(&q1), black_box(&q2), 0., 1e-3, black_box(4e-4)))
+    });
+}
+
+fn bench_interpolation(c: &mut Criterion) {
+    // Sixteen balls recorded at every step. The lookup is done near the end
+    // of the sequence, where the states are searched longest.
+    c.bench(
+        "calc_interpolated_at",
+        ParameterizedBenchmark::new(
+            "states",
+            |b, &n_states| {
+                let mut simulator = scattered(16);
+                let seq = SimulationStateSeq {
+                    states: (0..n_states).map(|_| simulator.progress()).collect(),
+                };
+                let t = seq.states[n_states * 9 / 10].t + 0.5e-4;
+                b.iter(|| seq.calc_interpolated_at(black_box(t)))
+            },
+            vec![100, 1000, 10000],
+        ),
+    );
+}
+
+fn bench_complete_shots(c: &mut Criterion) {
+    // Each iteration plays a whole shot, so only a few samples are taken.
+    let names: Vec<&'static str> = golden::catalogue().iter().map(|reference| reference.name).collect();
+    c.bench(
+        "complete_shot",
+        ParameterizedBenchmark::new(
+            "reference",
+            |b, name| {
+                let reference = golden::catalogue().into_iter().find(|r| r.name == *name).unwrap();
+                b.iter(|| {
+                    let (mut simulator, _) = reference.scene.clone().into_simulator(1e-4);
+                    simulator.apply_shot(&reference.shot);
+                    simulator.run_until_rest(30.);
+                    simulator.t
+                })
+            },
+            names,
+        ).sample_size(10),
+    );
+}
+
+criterion_group!(benches, bench_quaternions, bench_interpolation, bench_complete_shots);
+criterion_main!(benches);
@@ -0,0 +1,219 @@
+// How the balls look when drawn. Nothing here affects the simulation; the
+// spots are there so that the rotation of a ball can be seen, and can be laid
+// out like the ones on the training cue balls players practise with.
+
+use geometry::{JUnitVector3, JVector3};
+use jlib::consts;
+use rules::CUE_BALL;
+use rules::snooker;
+use rules::three_cushion;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+pub const WHITE: Colour = Colour { r: 255, g: 255, b: 255 };
+pub const BLACK: Colour = Colour { r: 20, g: 20, b: 20 };
+pub const RED: Colour = Colour { r: 200, g: 20, b: 20 };
+pub const YELLOW: Colour = Colour { r: 250, g: 200, b: 20 };
+pub const GREEN: Colour = Colour { r: 20, g: 130, b: 50 };
+pub const BROWN: Colour = Colour { r: 120, g: 70, b: 30 };
+pub const BLUE: Colour = Colour { r: 20, g: 60, b: 190 };
+pub const PINK: Colour = Colour { r: 250, g: 130, b: 170 };
+pub const PURPLE: Colour = Colour { r: 90, g: 30, b: 140 };
+pub const ORANGE: Colour = Colour { r: 250, g: 120, b: 20 };
+pub const MAROON: Colour = Colour { r: 120, g: 20, b: 30 };
+
+// Of the ball radius.
+pub const SPOT_RADIUS_FACTOR: f64 = 0.1;
+pub const MEASLE_SPOT_RADIUS_FACTOR: f64 = 0.08;
+pub const MEASLE_SPOTS: usize = 32;
+// The white caps at the poles of a striped pool ball.
+pub const STRIPE_CAP_RADIUS_FACTOR: f64 = 0.55;
+
+// A round mark on the surface of the ball. direction points from the centre
+// of the ball to the centre of the mark, with the ball at rest.
+#[derive(Clone, Debug)]
+pub struct Spot {
+    pub direction: JUnitVector3,
+    // Of the ball radius.
+    pub radius_factor: f64,
+    pub colour: Colour,
+}
+
+#[derive(Clone, Debug)]
+pub struct BallAppearance {
+    pub colour: Colour,
+    pub spots: Vec<Spot>,
+}
+
+impl BallAppearance {
+    pub fn plain(colour: Colour) -> Self {
+        BallAppearance { colour: colour, spots: vec![] }
+    }
+
+    // directions need not be normalized.
+    pub fn with_spots(colour: Colour, directions: &[JVector3], spot_colour: Colour, radius_factor: f64) -> Self {
+        BallAppearance {
+            colour: colour,
+            spots: directions.iter().map(|direction| Spot {
+                direction: JUnitVector3::new_normalize(*direction),
+                radius_factor: radius_factor,
+                colour: spot_colour,
+            }).collect(),
+        }
+    }
+
+    // A spot on the top and one on the bottom, which shows side spin and
+    // any tilt of the rotation axis.
+    pub fn poles(colour: Colour, spot_colour: Colour) -> Self {
+        let directions = [JVector3::new(0., 0., 1.), JVector3::new(0., 0., -1.)];
+        BallAppearance::with_spots(colour, &directions, spot_colour, SPOT_RADIUS_FACTOR)
+    }
+
+    // A white ball with six spots on the axes, as on the Aramith Pro Cup cue
+    // ball. Whatever the spin, some spot is near the rotation axis and some
+    // spot is far from it.
+    pub fn six_spot(spot_colour: Colour) -> Self {
+        let directions = [
+            JVector3::new(1., 0., 0.),
+            JVector3::new(-1., 0., 0.),
+            JVector3::new(0., 1., 0.),
+            JVector3::new(0., -1., 0.),
+            JVector3::new(0., 0., 1.),
+            JVector3::new(0., 0., -1.),
+        ];
+        BallAppearance::with_spots(WHITE, &directions, spot_colour, SPOT_RADIUS_FACTOR)
+    }
+
+    // A white ball covered with small spots spread evenly over its surface,
+    // like the measle training cue balls, so that the rotation shows from any
+    // side. The spots lie on a Fibonacci lattice.
+    pub fn measle(spot_colour: Colour) -> Self {
+        let golden_angle = consts::PI * (3. - 5f64.sqrt());
+        let n = MEASLE_SPOTS as f64;
+        let directions: Vec<JVector3> = (0..MEASLE_SPOTS).map(|k| {
+            let z = 1. - (2. * k as f64 + 1.) / n;
+            let r = (1. - z * z).sqrt();
+            let phi = golden_angle * k as f64;
+            JVector3::new(r * phi.cos(), r * phi.sin(), z)
+        }).collect();
+        BallAppearance::with_spots(WHITE, &directions, spot_colour, MEASLE_SPOT_RADIUS_FACTOR)
+    }
+
+    // A striped pool ball: the band in colour and white caps at the poles.
+    pub fn stripe(colour: Colour) -> Self {
+        let directions = [JVector3::new(0., 0., 1.), JVector3::new(0., 0., -1.)];
+        BallAppearance::with_spots(colour, &directions, WHITE, STRIPE_CAP_RADIUS_FACTOR)
+    }
+}
+
+// The appearances of the balls of a game, by ball number. Balls without an
+// appearance of their own look like default.
+#[derive(Clone, Debug)]
+pub struct BallSet {
+    pub default: BallAppearance,
+    pub balls: Vec<(u32, BallAppearance)>,
+}
+
+impl BallSet {
+    pub fn uniform(appearance: BallAppearance) -> Self {
+        BallSet { default: appearance, balls: vec![] }
+    }
+
+    // Replaces the appearance the ball had before, if any.
+    pub fn set(&mut self, number: u32, appearance: BallAppearance) {
+        match self.balls.iter().position(|&(n, _)| n == number) {
+            Some(k) => self.balls[k].1 = appearance,
+            None => self.balls.push((number, appearance)),
+        }
+    }
+
+    pub fn appearance_of(&self, number: u32) -> &BallAppearance {
+        self.balls.iter()
+            .find(|&&(n, _)| n == number)
+            .map(|&(_, ref appearance)| appearance)
+            .unwrap_or(&self.default)
+    }
+
+    // Numbered pool balls: solids 1 to 8 and stripes 9 to 15.
+    pub fn pool(cue_ball: BallAppearance) -> Self {
+        let colours = [YELLOW, BLUE, RED, PURPLE, ORANGE, GREEN, MAROON];
+        let mut set = BallSet::uniform(BallAppearance::plain(BLACK));
+        set.set(CUE_BALL, cue_ball);
+        for (k, &colour) in colours.iter().enumerate() {
+            set.set(k as u32 + 1, BallAppearance::plain(colour));
+            set.set(k as u32 + 9, BallAppearance::stripe(colour));
+        }
+        set
+    }
+
+    // Numbered as in rules::snooker. The reds are the default.
+    pub fn snooker(cue_ball: BallAppearance) -> Self {
+        let mut set = BallSet::uniform(BallAppearance::plain(RED));
+        set.set(CUE_BALL, cue_ball);
+        set.set(snooker::YELLOW, BallAppearance::plain(YELLOW));
+        set.set(snooker::GREEN, BallAppearance::plain(GREEN));
+        set.set(snooker::BROWN, BallAppearance::plain(BROWN));
+        set.set(snooker::BLUE, BallAppearance::plain(BLUE));
+        set.set(snooker::PINK, BallAppearance::plain(PINK));
+        set.set(snooker::BLACK, BallAppearance::plain(BLACK));
+        set
+    }
+
+    // Numbered as in rules::three_cushion. Either of white and yellow can be
+    // the cue ball, so both are given.
+    pub fn carom(white: BallAppearance, yellow: BallAppearance) -> Self {
+        let mut set = BallSet::uniform(BallAppearance::plain(RED));
+        set.set(three_cushion::WHITE, white);
+        set.set(three_cushion::YELLOW, yellow);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, prng::XorShiftRng};
+    use appearance::{BallAppearance, BallSet, MEASLE_SPOTS, RED, WHITE, YELLOW};
+    use geometry::JVector3;
+    use rules::snooker;
+
+    #[test]
+    fn test_measle_spots_cover_the_ball() {
+        let appearance = BallAppearance::measle(RED);
+        assert_eq!(appearance.spots.len(), MEASLE_SPOTS);
+
+        // No two spots close together and no direction far from a spot.
+        let directions: Vec<JVector3> = appearance.spots.iter().map(|spot| spot.direction.unwrap()).collect();
+        for (i, a) in directions.iter().enumerate() {
+            for b in directions[i + 1..].iter() {
+                assert!(a.angle(b) > 0.5);
+            }
+        }
+        let mut rng = XorShiftRng::from_seed([5; 16]);
+        for _ in 0..1000 {
+            let v = JVector3::new(rng.gen_range(-1., 1.), rng.gen_range(-1., 1.), rng.gen_range(-1., 1.));
+            let nearest = directions.iter().map(|d| d.angle(&v)).fold(::std::f64::MAX, f64::min);
+            assert!(nearest < 0.45);
+        }
+    }
+
+    #[test]
+    fn test_ball_sets() {
+        let set = BallSet::snooker(BallAppearance::six_spot(RED));
+        assert_eq!(set.appearance_of(0).spots.len(), 6);
+        assert_eq!(set.appearance_of(snooker::YELLOW).colour, YELLOW);
+        assert_eq!(set.appearance_of(snooker::FIRST_RED + 3).colour, RED);
+
+        let mut set = BallSet::pool(BallAppearance::plain(WHITE));
+        assert_eq!(set.balls.len(), 15);
+        assert_eq!(set.appearance_of(9).colour, set.appearance_of(1).colour);
+        assert_eq!(set.appearance_of(9).spots[0].colour, WHITE);
+        set.set(0, BallAppearance::measle(RED));
+        assert_eq!(set.balls.len(), 15);
+        assert_eq!(set.appearance_of(0).spots.len(), 32);
+    }
+}
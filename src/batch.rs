@@ -0,0 +1,189 @@
+// Runs many variations of a shot on the same scene, for parameter sweeps.
+// Every shot gets its own Simulator, built from a copy of the scene, so the
+// runs are independent of each other and of how they are spread over threads.
+// Summaries are returned in the order of the shots.
+
+use std::sync::Arc;
+use std::thread;
+
+use cloth::ClothPreset;
+use geometry::JVector3;
+use jlib::{Shot, SimulationEvent};
+use rules::Scene;
+
+#[derive(Clone)]
+pub struct BatchConf {
+    pub ts: f64,
+    // A run stops when everything is at rest or after this many seconds.
+    pub horizon: f64,
+    pub threads: usize,
+    // Runs every simulator in deterministic mode, so that summaries are
+    // bit-identical across platforms as well as across thread counts.
+    pub deterministic: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventCounts {
+    pub ball_ball: usize,
+    pub ball_cloth: usize,
+    pub ball_cushion: usize,
+    pub ball_pocket: usize,
+}
+
+impl EventCounts {
+    pub fn from_events(events: &[SimulationEvent]) -> Self {
+        let mut counts = EventCounts::default();
+        for event in events.iter() {
+            match *event {
+                SimulationEvent::BallBall { .. } => counts.ball_ball += 1,
+                SimulationEvent::BallCloth { .. } => counts.ball_cloth += 1,
+                SimulationEvent::BallCushion { .. } => counts.ball_cushion += 1,
+                SimulationEvent::BallPocket { .. } => counts.ball_pocket += 1,
+            }
+        }
+        counts
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShotSummary {
+    // Final positions, in the order of Scene::balls. Pocketed balls keep the
+    // position at which they dropped.
+    pub positions: Vec<JVector3>,
+    // Numbers of the pocketed balls, in the order they dropped.
+    pub pocketed: Vec<u32>,
+    pub event_counts: EventCounts,
+    // Time at which the run stopped.
+    pub t: f64,
+    pub came_to_rest: bool,
+    // Simulator::state_hash of the final state.
+    pub state_hash: u64,
+}
+
+pub fn run_shot(scene: &Scene, shot: &Shot, conf: &BatchConf) -> ShotSummary {
+    let (mut simulator, numbers) = scene.clone().into_simulator(conf.ts);
+    simulator.deterministic = conf.deterministic;
+    simulator.apply_shot(shot);
+    simulator.run_until_rest(conf.horizon);
+
+    let pocketed = simulator.events.iter().filter_map(|event| match *event {
+        SimulationEvent::BallPocket { i, .. } => Some(numbers[i]),
+        _ => None,
+    }).collect();
+
+    ShotSummary {
+        positions: simulator.balls.iter().map(|ball| ball.pos).collect(),
+        pocketed: pocketed,
+        event_counts: EventCounts::from_events(&simulator.events),
+        t: simulator.t,
+        came_to_rest: simulator.is_at_rest(),
+        state_hash: simulator.state_hash(),
+    }
+}
+
+// Returns one summary per shot, with summaries[k] belonging to shots[k]
+// whatever the number of threads.
+pub fn run_batch(scene: &Scene, shots: &[Shot], conf: &BatchConf) -> Vec<ShotSummary> {
+    let scene = Arc::new(scene.clone());
+    run_jobs(shots.iter().map(|shot| (Arc::clone(&scene), shot.clone())).collect(), conf)
+}
+
+// Plays the same shot on the scene once with each cloth, for a side-by-side
+// comparison. summaries[k] belongs to presets[k].
+pub fn compare_cloths(
+    scene: &Scene,
+    shot: &Shot,
+    presets: &[ClothPreset],
+    conf: &BatchConf,
+) -> Vec<ShotSummary> {
+    run_jobs(presets.iter().map(|preset| {
+        let mut scene = scene.clone();
+        preset.apply(&mut scene.world_conf);
+        (Arc::new(scene), shot.clone())
+    }).collect(), conf)
+}
+
+fn run_jobs(jobs: Vec<(Arc<Scene>, Shot)>, conf: &BatchConf) -> Vec<ShotSummary> {
+    if conf.threads == 0 {
+        panic!("batch run with zero threads");
+    }
+    if jobs.is_empty() {
+        return Vec::new();
+    }
+
+    // Each thread takes a contiguous chunk of the jobs, and the chunks are
+    // joined back in order.
+    let chunk_len = (jobs.len() + conf.threads - 1) / conf.threads;
+    let n_jobs = jobs.len();
+    let handles: Vec<_> = jobs.chunks(chunk_len).map(|chunk| {
+        let chunk = chunk.to_vec();
+        let conf = conf.clone();
+        thread::spawn(move || {
+            chunk.iter().map(|&(ref scene, ref shot)| run_shot(scene, shot, &conf)).collect::<Vec<_>>()
+        })
+    }).collect();
+
+    let mut summaries = Vec::with_capacity(n_jobs);
+    for handle in handles {
+        summaries.extend(handle.join().expect("a batch thread panicked"));
+    }
+    summaries
+}
+
+#[cfg(test)]
+mod tests {
+    use batch::{BatchConf, compare_cloths, run_batch, run_shot};
+    use cloth::{self, ClothPreset};
+    use fixtures;
+    use geometry::JVector3;
+    use jlib::Shot;
+
+    // Shots along the diagonal at increasing angles off it.
+    fn sweep(n: usize) -> Vec<Shot> {
+        (0..n).map(|k| {
+            let angle = 45f64.to_radians() + k as f64 * 0.02;
+            Shot {
+                ball: 0,
+                u: JVector3::new(angle.cos(), angle.sin(), 0.) * 1.5,
+                omega: JVector3::zeros(),
+            }
+        }).collect()
+    }
+
+    fn conf(threads: usize) -> BatchConf {
+        BatchConf { ts: 1e-3, horizon: 2., threads: threads, deterministic: true }
+    }
+
+    #[test]
+    fn test_straight_shot_pockets_object_ball() {
+        let summary = run_shot(&fixtures::corner_pot(), &sweep(1)[0], &conf(1));
+        assert_eq!(summary.pocketed[0], 3);
+        assert_eq!(summary.event_counts.ball_pocket, summary.pocketed.len());
+        assert!(summary.event_counts.ball_ball >= 1);
+    }
+
+    #[test]
+    fn test_results_do_not_depend_on_threads() {
+        let shots = sweep(10);
+        let sequential: Vec<_> = shots.iter().map(|shot| run_shot(&fixtures::corner_pot(), shot, &conf(1))).collect();
+        for &threads in [1, 3, 4, 16].iter() {
+            assert_eq!(run_batch(&fixtures::corner_pot(), &shots, &conf(threads)), sequential);
+        }
+        assert!(run_batch(&fixtures::corner_pot(), &[], &conf(4)).is_empty());
+    }
+
+    #[test]
+    fn test_compare_cloths() {
+        let mut shot = sweep(1)[0].clone();
+        shot.u *= 0.5;
+        let summaries = compare_cloths(&fixtures::corner_pot(), &shot, &cloth::ALL, &conf(2));
+        assert_eq!(summaries.len(), cloth::ALL.len());
+
+        // The object ball travels further on new cloth than on bar-box felt.
+        let travel = |preset: ClothPreset| {
+            let k = cloth::ALL.iter().position(|&p| p == preset).unwrap();
+            (summaries[k].positions[1] - fixtures::corner_pot().balls[1].pos).norm()
+        };
+        assert!(travel(ClothPreset::NewSimonis) > travel(ClothPreset::BarBox));
+    }
+}
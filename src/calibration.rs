@@ -0,0 +1,395 @@
+// Fits WorldConf parameters to measured trajectories. The scene and the shot
+// have to reproduce the start of the measurement; the parameters are then
+// varied until the simulated positions are as close as possible to the
+// measured ones.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+
+use jlib::{Shot, WorldConf};
+use rules::Scene;
+
+// A measured position of a ball. Only x and y are measured, as from a camera
+// above the table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub t: f64,
+    pub ball: u32,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    BallBallRest,
+    BallClothRest,
+    BallCushionRest,
+    BallClothFriction,
+    BallClothRollingResistance,
+    BallClothSpinDecay,
+}
+
+impl Parameter {
+    // The names are those of the fields, as in scene files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Parameter::BallBallRest => "ball_ball_rest",
+            Parameter::BallClothRest => "ball_cloth_rest",
+            Parameter::BallCushionRest => "ball_cushion_rest",
+            Parameter::BallClothFriction => "ball_cloth_friction",
+            Parameter::BallClothRollingResistance => "ball_cloth_rolling_resistance",
+            Parameter::BallClothSpinDecay => "ball_cloth_spin_decay",
+        }
+    }
+
+    // The interval searched, a little wider than the values found on real
+    // tables.
+    pub fn range(&self) -> (f64, f64) {
+        match *self {
+            Parameter::BallBallRest => (0.8, 1.),
+            Parameter::BallClothRest => (0.2, 0.8),
+            Parameter::BallCushionRest => (0.5, 0.95),
+            Parameter::BallClothFriction => (0.1, 0.4),
+            Parameter::BallClothRollingResistance => (0.001, 0.03),
+            Parameter::BallClothSpinDecay => (1., 20.),
+        }
+    }
+
+    pub fn get(&self, world_conf: &WorldConf) -> f64 {
+        match *self {
+            Parameter::BallBallRest => world_conf.ball_ball_rest,
+            Parameter::BallClothRest => world_conf.ball_cloth_rest,
+            Parameter::BallCushionRest => match world_conf.table {
+                Some(ref table) => table.ball_cushion_rest,
+                None => panic!("ball_cushion_rest of a world without a table"),
+            },
+            Parameter::BallClothFriction => world_conf.ball_cloth_friction,
+            Parameter::BallClothRollingResistance => world_conf.ball_cloth_rolling_resistance,
+            Parameter::BallClothSpinDecay => world_conf.ball_cloth_spin_decay,
+        }
+    }
+
+    pub fn set(&self, world_conf: &mut WorldConf, value: f64) {
+        match *self {
+            Parameter::BallBallRest => world_conf.ball_ball_rest = value,
+            Parameter::BallClothRest => world_conf.ball_cloth_rest = value,
+            Parameter::BallCushionRest => match world_conf.table {
+                Some(ref mut table) => table.ball_cushion_rest = value,
+                None => panic!("ball_cushion_rest of a world without a table"),
+            },
+            Parameter::BallClothFriction => world_conf.ball_cloth_friction = value,
+            Parameter::BallClothRollingResistance => world_conf.ball_cloth_rolling_resistance = value,
+            Parameter::BallClothSpinDecay => world_conf.ball_cloth_spin_decay = value,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CalibrationConf {
+    pub ts: f64,
+    // Each evaluation is a full simulation of the shot.
+    pub max_evaluations: usize,
+    // The search stops when every parameter is known to within this
+    // fraction of its range.
+    pub tolerance: f64,
+}
+
+pub struct Calibration {
+    pub values: Vec<(Parameter, f64)>,
+    pub world_conf: WorldConf,
+    // Distance between the simulated and the measured position of every
+    // observation used; see residuals.
+    pub residuals: Vec<f64>,
+    pub rms_error: f64,
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    // index counts from 0, in the order the observations were given.
+    NotFinite { index: usize },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationError::NotFinite { index } => {
+                write!(f, "observation {} has a time or position that is not a finite number", index)
+            }
+        }
+    }
+}
+
+// The observations that can be compared with the simulation. Observations of
+// balls that are not in the scene are skipped, as in tracking::deviations.
+fn usable_observations(scene: &Scene, observations: &[Observation]) -> Result<Vec<Observation>, CalibrationError> {
+    let mut usable = Vec::new();
+    for (index, observation) in observations.iter().enumerate() {
+        if !(observation.t.is_finite() && observation.x.is_finite() && observation.y.is_finite()) {
+            return Err(CalibrationError::NotFinite { index: index });
+        }
+        if scene.numbers.contains(&observation.ball) {
+            usable.push(observation.clone());
+        }
+    }
+    Ok(usable)
+}
+
+// A total order of errors, with NaN, as from a simulation that diverged, after
+// everything else.
+fn compare_errors(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).unwrap(),
+        (a_is_nan, b_is_nan) => a_is_nan.cmp(&b_is_nan),
+    }
+}
+
+// Distance of the simulated position from every observation of a ball in the
+// scene, in the order of the observations.
+pub fn residuals(
+    scene: &Scene,
+    shot: &Shot,
+    observations: &[Observation],
+    ts: f64,
+) -> Result<Vec<f64>, CalibrationError> {
+    let observations = usable_observations(scene, observations)?;
+    Ok(simulated_residuals(scene, shot, &observations, ts))
+}
+
+// observations have to be usable_observations.
+fn simulated_residuals(scene: &Scene, shot: &Shot, observations: &[Observation], ts: f64) -> Vec<f64> {
+    let (mut simulator, numbers) = scene.clone().into_simulator(ts);
+    simulator.apply_shot(shot);
+
+    let mut order: Vec<usize> = (0..observations.len()).collect();
+    order.sort_by(|&a, &b| observations[a].t.partial_cmp(&observations[b].t).unwrap());
+
+    let mut residuals = vec![0.; observations.len()];
+    for k in order {
+        let observation = &observations[k];
+        let i = numbers.iter().position(|&number| number == observation.ball).unwrap();
+        simulator.run_until_rest(observation.t);
+        let pos = simulator.balls[i].pos;
+        residuals[k] = ((pos.x - observation.x).powi(2) + (pos.y - observation.y).powi(2)).sqrt();
+    }
+    residuals
+}
+
+fn rms(residuals: &[f64]) -> f64 {
+    if residuals.is_empty() {
+        return 0.;
+    }
+    (residuals.iter().map(|r| r * r).sum::<f64>() / residuals.len() as f64).sqrt()
+}
+
+pub fn calibrate(
+    scene: &Scene,
+    shot: &Shot,
+    observations: &[Observation],
+    parameters: &[Parameter],
+    conf: &CalibrationConf,
+) -> Result<Calibration, CalibrationError> {
+    // Nelder-Mead, which needs nothing but the error itself. It works on the
+    // parameters scaled to [0, 1] over their ranges, so that one tolerance
+    // fits all of them, and values outside the ranges are clamped. The
+    // search starts from the values in the scene.
+    let observations = &usable_observations(scene, observations)?;
+    let mut scene = scene.clone();
+    if parameters.is_empty() {
+        let residuals = simulated_residuals(&scene, shot, observations, conf.ts);
+        return Ok(Calibration {
+            values: vec![],
+            world_conf: scene.world_conf,
+            rms_error: rms(&residuals),
+            residuals: residuals,
+        });
+    }
+
+    let n = parameters.len();
+    let evaluations = Cell::new(0);
+    let to_value = |p: &Parameter, x: f64| {
+        let (min, max) = p.range();
+        min + x.max(0.).min(1.) * (max - min)
+    };
+    let error_at = |scene: &mut Scene, x: &[f64]| {
+        for (p, &x) in parameters.iter().zip(x.iter()) {
+            p.set(&mut scene.world_conf, to_value(p, x));
+        }
+        evaluations.set(evaluations.get() + 1);
+        rms(&simulated_residuals(scene, shot, observations, conf.ts))
+    };
+
+    let start: Vec<f64> = parameters.iter().map(|p| {
+        let (min, max) = p.range();
+        (p.get(&scene.world_conf) - min) / (max - min)
+    }).collect();
+    let mut simplex = vec![start.clone()];
+    for k in 0..n {
+        let mut vertex = start.clone();
+        vertex[k] += if vertex[k] < 0.5 { 0.1 } else { -0.1 };
+        simplex.push(vertex);
+    }
+    let mut errors: Vec<f64> = simplex.iter().map(|x| error_at(&mut scene, x)).collect();
+
+    let combine = |a: &[f64], b: &[f64], w: f64| -> Vec<f64> {
+        a.iter().zip(b.iter()).map(|(a, b)| a + w * (b - a)).collect()
+    };
+    while evaluations.get() < conf.max_evaluations {
+        let mut order: Vec<usize> = (0..n + 1).collect();
+        order.sort_by(|&a, &b| compare_errors(errors[a], errors[b]));
+        simplex = order.iter().map(|&k| simplex[k].clone()).collect();
+        errors = order.iter().map(|&k| errors[k]).collect();
+
+        let size = simplex[1..].iter()
+            .flat_map(|x| x.iter().zip(simplex[0].iter()).map(|(a, b)| (a - b).abs()))
+            .fold(0., f64::max);
+        if size < conf.tolerance {
+            break;
+        }
+
+        // Reflect the worst vertex through the centroid of the others, then
+        // expand, contract or shrink depending on how that turns out.
+        let mut centroid = vec![0.; n];
+        for x in simplex[..n].iter() {
+            for k in 0..n {
+                centroid[k] += x[k] / n as f64;
+            }
+        }
+        let reflected = combine(&centroid, &simplex[n], -1.);
+        let error_reflected = error_at(&mut scene, &reflected);
+        if error_reflected < errors[0] {
+            let expanded = combine(&centroid, &simplex[n], -2.);
+            let error_expanded = error_at(&mut scene, &expanded);
+            if error_expanded < error_reflected {
+                simplex[n] = expanded;
+                errors[n] = error_expanded;
+            }
+            else {
+                simplex[n] = reflected;
+                errors[n] = error_reflected;
+            }
+        }
+        else if error_reflected < errors[n - 1] {
+            simplex[n] = reflected;
+            errors[n] = error_reflected;
+        }
+        else {
+            let contracted = combine(&centroid, &simplex[n], 0.5);
+            let error_contracted = error_at(&mut scene, &contracted);
+            if error_contracted < errors[n] {
+                simplex[n] = contracted;
+                errors[n] = error_contracted;
+            }
+            else {
+                for k in 1..n + 1 {
+                    simplex[k] = combine(&simplex[0], &simplex[k], 0.5);
+                    errors[k] = error_at(&mut scene, &simplex[k]);
+                }
+            }
+        }
+    }
+
+    let best = (0..n + 1).min_by(|&a, &b| compare_errors(errors[a], errors[b])).unwrap();
+    for (p, &x) in parameters.iter().zip(simplex[best].iter()) {
+        p.set(&mut scene.world_conf, to_value(p, x));
+    }
+    let residuals = simulated_residuals(&scene, shot, observations, conf.ts);
+    Ok(Calibration {
+        values: parameters.iter().map(|p| (*p, p.get(&scene.world_conf))).collect(),
+        world_conf: scene.world_conf,
+        rms_error: rms(&residuals),
+        residuals: residuals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use calibration::{CalibrationConf, CalibrationError, Observation, Parameter, calibrate, compare_errors, residuals};
+    use geometry::JVector3;
+    use jlib::Shot;
+    use rules::Scene;
+    use scene_file;
+
+    fn scene() -> Scene {
+        scene_file::parse("table open\nball 0 0 0").unwrap()
+    }
+
+    fn shot() -> Shot {
+        Shot { ball: 0, u: JVector3::new(1.5, 0., 0.), omega: JVector3::zeros() }
+    }
+
+    // Positions every tenth of a second, as a tracker would report them.
+    fn observe(scene: &Scene) -> Vec<Observation> {
+        let (mut simulator, _) = scene.clone().into_simulator(1e-3);
+        simulator.apply_shot(&shot());
+        (1..30).map(|k| {
+            let t = k as f64 * 0.1;
+            simulator.run_until_rest(t);
+            Observation { t: t, ball: 0, x: simulator.balls[0].pos.x, y: simulator.balls[0].pos.y }
+        }).collect()
+    }
+
+    #[test]
+    fn test_residuals() {
+        let mut observations = observe(&scene());
+        observations.reverse();
+        assert!(residuals(&scene(), &shot(), &observations, 1e-3).unwrap().iter().all(|&r| r == 0.));
+
+        observations[0].y += 0.01;
+        assert!((residuals(&scene(), &shot(), &observations, 1e-3).unwrap()[0] - 0.01).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calibrate_recovers_parameters() {
+        let mut measured = scene();
+        measured.world_conf.ball_cloth_friction = 0.25;
+        measured.world_conf.ball_cloth_rolling_resistance = 0.015;
+        let observations = observe(&measured);
+
+        let parameters = [Parameter::BallClothFriction, Parameter::BallClothRollingResistance];
+        let conf = CalibrationConf { ts: 1e-3, max_evaluations: 200, tolerance: 1e-4 };
+        let calibration = calibrate(&scene(), &shot(), &observations, &parameters, &conf).unwrap();
+
+        assert!((calibration.values[0].1 - 0.25).abs() < 0.005);
+        assert!((calibration.values[1].1 - 0.015).abs() < 0.0002);
+        assert_eq!(calibration.world_conf.ball_cloth_friction, calibration.values[0].1);
+        assert_eq!(calibration.residuals.len(), observations.len());
+        assert!(calibration.rms_error < 1e-3);
+    }
+
+    #[test]
+    fn test_errors_sort_with_nan_last() {
+        let nan = ::std::f64::NAN;
+        let mut errors = vec![0.3, nan, 0.1, ::std::f64::INFINITY, nan, 0.2];
+        errors.sort_by(|&a, &b| compare_errors(a, b));
+        assert_eq!(&errors[..4], &[0.1, 0.2, 0.3, ::std::f64::INFINITY]);
+        assert!(errors[4].is_nan() && errors[5].is_nan());
+    }
+
+    #[test]
+    fn test_non_finite_observations_and_unknown_balls() {
+        let mut observations = observe(&scene());
+        observations[3].t = ::std::f64::NAN;
+        match residuals(&scene(), &shot(), &observations, 1e-3) {
+            Err(CalibrationError::NotFinite { index: 3 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let conf = CalibrationConf { ts: 1e-3, max_evaluations: 10, tolerance: 1e-4 };
+        assert!(calibrate(&scene(), &shot(), &observations, &[Parameter::BallClothFriction], &conf).is_err());
+
+        // Balls that are not in the scene are left out.
+        let mut observations = observe(&scene());
+        let n = observations.len();
+        observations.insert(2, Observation { t: 0.25, ball: 9, x: 1., y: 1. });
+        assert_eq!(residuals(&scene(), &shot(), &observations, 1e-3).unwrap(), vec![0.; n]);
+    }
+
+    #[test]
+    fn test_calibrate_without_parameters() {
+        let observations = observe(&scene());
+        let conf = CalibrationConf { ts: 1e-3, max_evaluations: 10, tolerance: 0. };
+        let calibration = calibrate(&scene(), &shot(), &observations, &[], &conf).unwrap();
+        assert!(calibration.values.is_empty());
+        assert_eq!(calibration.rms_error, 0.);
+    }
+}
@@ -0,0 +1,90 @@
+// Named cloth conditions. A preset sets the ball-to-cloth parameters of a
+// WorldConf, so that a shot can be tried on fast new cloth and on slow worn
+// cloth without tuning each parameter by hand. The values are within the
+// ranges listed in jlib::consts.
+
+use geometry::{JUnitVector3, JVector3};
+use jlib::{Nap, WorldConf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClothPreset {
+    // Fast worsted pool cloth, as fitted.
+    NewSimonis,
+    // Worsted cloth after a few years of play.
+    Worn,
+    // Slow woollen felt of coin-operated bar tables.
+    BarBox,
+    // Napped snooker cloth, with the nap running from the baulk end towards
+    // the top cushion.
+    SnookerNapped,
+}
+
+pub const ALL: [ClothPreset; 4] = [
+    ClothPreset::NewSimonis,
+    ClothPreset::Worn,
+    ClothPreset::BarBox,
+    ClothPreset::SnookerNapped,
+];
+
+impl ClothPreset {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ClothPreset::NewSimonis => "new_simonis",
+            ClothPreset::Worn => "worn",
+            ClothPreset::BarBox => "bar_box",
+            ClothPreset::SnookerNapped => "snooker_napped",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ClothPreset> {
+        ALL.iter().cloned().find(|preset| preset.name() == name)
+    }
+
+    pub fn apply(&self, world_conf: &mut WorldConf) {
+        // (sliding friction, rolling resistance, spin decay, restitution)
+        let (friction, rolling_resistance, spin_decay, rest) = match *self {
+            ClothPreset::NewSimonis => (0.2, 0.005, 5., 0.5),
+            ClothPreset::Worn => (0.25, 0.012, 12., 0.45),
+            ClothPreset::BarBox => (0.3, 0.015, 15., 0.4),
+            ClothPreset::SnookerNapped => (0.2, 0.008, 8., 0.5),
+        };
+        world_conf.ball_cloth_friction = friction;
+        world_conf.ball_cloth_rolling_resistance = rolling_resistance;
+        world_conf.ball_cloth_spin_decay = spin_decay;
+        world_conf.ball_cloth_rest = rest;
+
+        world_conf.conditions.nap = match *self {
+            ClothPreset::SnookerNapped => Some(Nap {
+                direction: JUnitVector3::new_normalize(JVector3::new(1., 0., 0.)),
+                strength: 0.005,
+                max_speed: 0.3,
+            }),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cloth::{ALL, ClothPreset};
+    use rules::three_cushion::default_scene;
+
+    #[test]
+    fn test_names_round_trip() {
+        for preset in ALL.iter() {
+            assert_eq!(ClothPreset::from_name(preset.name()), Some(*preset));
+        }
+        assert_eq!(ClothPreset::from_name("baize"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut world_conf = default_scene().world_conf;
+        ClothPreset::SnookerNapped.apply(&mut world_conf);
+        assert!(world_conf.conditions.nap.is_some());
+
+        ClothPreset::BarBox.apply(&mut world_conf);
+        assert!(world_conf.conditions.nap.is_none());
+        assert_eq!(world_conf.ball_cloth_rolling_resistance, 0.015);
+    }
+}
@@ -0,0 +1,20 @@
+// Scenes used by the tests of more than one module.
+
+use geometry::JVector3;
+use jlib::{Ball, WorldConf, consts};
+use rules::Scene;
+
+// Cue ball and ball 3 on a pool table, on the diagonal through the corner
+// pocket at (length/2, width/2), 0.6 m and 0.3 m from it along each axis.
+pub fn corner_pot() -> Scene {
+    let r = consts::POOL_BALL_RADIUS;
+    let corner = JVector3::new(consts::POOL_TABLE_LENGTH / 2., consts::POOL_TABLE_WIDTH / 2., r);
+    Scene {
+        world_conf: WorldConf::pool(),
+        balls: vec![
+            Ball::at_rest(corner - JVector3::new(0.6, 0.6, 0.)),
+            Ball::at_rest(corner - JVector3::new(0.3, 0.3, 0.)),
+        ],
+        numbers: vec![0, 3],
+    }
+}
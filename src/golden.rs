@@ -0,0 +1,461 @@
+// Regression harness for the physics. A catalogue of reference scenes is run
+// and compared with golden trajectories stored in tests/golden, one text file
+// per scene, so that a change to the simulation shows what moved. Each file
+// holds the positions of every ball at regular intervals and the full event
+// log:
+//
+//     sample 0.05 -0.6 0.1 0.0286 0.2 0 0.0286
+//     event ball_ball 0.412 0 1
+//     event ball_cushion 0.9 1 foot
+//
+// A sample line has the time followed by x, y and z of every ball. Lines
+// starting with # are comments. When a change to the physics is intended,
+// the files are rewritten by running the tests with JUSTSHOOT_UPDATE_GOLDEN
+// set in the environment.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use geometry::JVector3;
+use jlib::{CueStrike, Cushion, Shot, SimulationEvent};
+use rules::{three_cushion, snooker, Scene};
+use scene_file;
+
+// The reference scenes are run in deterministic mode with this timestep for
+// HORIZON_STEPS steps, whether or not the balls have come to rest by then,
+// and sampled every SAMPLE_EVERY steps.
+pub const TS: f64 = 1e-3;
+pub const HORIZON_STEPS: usize = 3000;
+pub const SAMPLE_EVERY: usize = 50;
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    // line counts from 1.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GoldenError::Io(ref e) => write!(f, "cannot read golden trajectory: {}", e),
+            GoldenError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for GoldenError {
+    fn from(e: io::Error) -> Self {
+        GoldenError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub t: f64,
+    // In the order of Scene::balls.
+    pub positions: Vec<JVector3>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    pub samples: Vec<Sample>,
+    pub events: Vec<SimulationEvent>,
+}
+
+fn cushion_name(cushion: &Cushion) -> &'static str {
+    match *cushion {
+        Cushion::Head => "head",
+        Cushion::Foot => "foot",
+        Cushion::Right => "right",
+        Cushion::Left => "left",
+    }
+}
+
+impl Trajectory {
+    pub fn record(scene: &Scene, shot: &Shot) -> Self {
+        let (mut simulator, _) = scene.clone().into_simulator(TS);
+        simulator.deterministic = true;
+        simulator.apply_shot(shot);
+
+        let mut samples = vec![Sample {
+            t: simulator.t,
+            positions: simulator.balls.iter().map(|ball| ball.pos).collect(),
+        }];
+        for k in 1..HORIZON_STEPS + 1 {
+            let state = simulator.progress();
+            if k % SAMPLE_EVERY == 0 {
+                samples.push(Sample {
+                    t: state.t,
+                    positions: state.balls.iter().map(|ball| ball.pos).collect(),
+                });
+            }
+        }
+        Trajectory { samples: samples, events: simulator.events }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GoldenError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Trajectory::parse(&text)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(b"# Golden trajectory, see src/golden.rs. Rewritten by the tests when\n")?;
+        file.write_all(b"# JUSTSHOOT_UPDATE_GOLDEN is set.\n")?;
+        file.write_all(self.to_text().as_bytes())
+    }
+
+    // f64 is written with Display, which reads back to the same value.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for sample in self.samples.iter() {
+            text.push_str(&format!("sample {}", sample.t));
+            for pos in sample.positions.iter() {
+                text.push_str(&format!(" {} {} {}", pos.x, pos.y, pos.z));
+            }
+            text.push('\n');
+        }
+        for event in self.events.iter() {
+            let line = match *event {
+                SimulationEvent::BallBall { t, i, j } => format!("ball_ball {} {} {}", t, i, j),
+                SimulationEvent::BallCloth { t, i } => format!("ball_cloth {} {}", t, i),
+                SimulationEvent::BallCushion { t, i, ref cushion } =>
+                    format!("ball_cushion {} {} {}", t, i, cushion_name(cushion)),
+                SimulationEvent::BallPocket { t, i, pocket } => format!("ball_pocket {} {} {}", t, i, pocket),
+            };
+            text.push_str(&format!("event {}\n", line));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, GoldenError> {
+        let mut samples = Vec::new();
+        let mut events = Vec::new();
+
+        for (k, line) in text.lines().enumerate() {
+            let error = |message: String| GoldenError::Parse { line: k + 1, message: message };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            let number = |i: usize| -> Result<f64, GoldenError> {
+                match words.get(i) {
+                    Some(word) => word.parse().map_err(|_| error(format!("{} is not a number", word))),
+                    None => Err(error("missing field".to_string())),
+                }
+            };
+            let index = |i: usize| -> Result<usize, GoldenError> {
+                match words.get(i) {
+                    Some(word) => word.parse().map_err(|_| error(format!("{} is not an index", word))),
+                    None => Err(error("missing field".to_string())),
+                }
+            };
+
+            match words[0] {
+                "sample" => {
+                    if words.len() % 3 != 2 {
+                        return Err(error("a sample has a time and three coordinates per ball".to_string()));
+                    }
+                    let mut positions = Vec::new();
+                    for b in 0..(words.len() - 2) / 3 {
+                        positions.push(JVector3::new(number(2 + 3 * b)?, number(3 + 3 * b)?, number(4 + 3 * b)?));
+                    }
+                    samples.push(Sample { t: number(1)?, positions: positions });
+                }
+                "event" => {
+                    let kind = words.get(1).cloned().unwrap_or("");
+                    let arity = match kind {
+                        "ball_cloth" => 2,
+                        "ball_ball" | "ball_cushion" | "ball_pocket" => 3,
+                        other => return Err(error(format!("unknown event {}", other))),
+                    };
+                    if words.len() != arity + 2 {
+                        return Err(error(format!("{} takes {} fields", kind, arity)));
+                    }
+                    let (t, i) = (number(2)?, index(3)?);
+                    events.push(match kind {
+                        "ball_ball" => SimulationEvent::BallBall { t: t, i: i, j: index(4)? },
+                        "ball_cloth" => SimulationEvent::BallCloth { t: t, i: i },
+                        "ball_pocket" => SimulationEvent::BallPocket { t: t, i: i, pocket: index(4)? },
+                        _ => {
+                            let cushion = match words[4] {
+                                "head" => Cushion::Head,
+                                "foot" => Cushion::Foot,
+                                "right" => Cushion::Right,
+                                "left" => Cushion::Left,
+                                other => return Err(error(format!("unknown cushion {}", other))),
+                            };
+                            SimulationEvent::BallCushion { t: t, i: i, cushion: cushion }
+                        }
+                    });
+                }
+                other => return Err(error(format!("unknown statement {}", other))),
+            }
+        }
+
+        Ok(Trajectory { samples: samples, events: events })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Tolerances {
+    // Metres, for every coordinate of every sample.
+    pub position: f64,
+    // Seconds between an expected event and the actual one.
+    pub event_time: f64,
+}
+
+impl Tolerances {
+    // Room for rounding differences between compilers and platforms, but
+    // not for a change to the physics. An event may move by less than one
+    // step.
+    pub fn standard() -> Self {
+        Tolerances { position: 1e-6, event_time: TS / 2. }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PositionError {
+    pub error: f64,
+    pub t: f64,
+    pub ball: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct EventDivergence {
+    // Position in the event log. Either event is None when one log ends
+    // before the other.
+    pub index: usize,
+    pub expected: Option<SimulationEvent>,
+    pub actual: Option<SimulationEvent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diff {
+    pub tolerances: Tolerances,
+    // None when there is nothing to compare, as when no sample is shared.
+    pub max_position_error: Option<PositionError>,
+    // Set when the number of samples or of balls differs.
+    pub shape_mismatch: Option<String>,
+    pub first_divergent_event: Option<EventDivergence>,
+}
+
+impl Diff {
+    pub fn passes(&self) -> bool {
+        self.shape_mismatch.is_none()
+            && self.first_divergent_event.is_none()
+            && self.max_position_error.as_ref().map_or(true, |e| e.error <= self.tolerances.position)
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref mismatch) = self.shape_mismatch {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        match self.max_position_error {
+            Some(ref e) => writeln!(
+                f, "  max position error {:e} m (ball {} at t = {} s), tolerance {:e} m",
+                e.error, e.ball, e.t, self.tolerances.position)?,
+            None => writeln!(f, "  no positions compared")?,
+        }
+        match self.first_divergent_event {
+            Some(ref divergence) => {
+                let show = |event: &Option<SimulationEvent>| match *event {
+                    Some(ref event) => format!("{:?}", event),
+                    None => "end of log".to_string(),
+                };
+                writeln!(f, "  first divergent event #{}:", divergence.index)?;
+                writeln!(f, "    expected {}", show(&divergence.expected))?;
+                writeln!(f, "    actual   {}", show(&divergence.actual))
+            }
+            None => writeln!(f, "  event logs match"),
+        }
+    }
+}
+
+fn event_matches(expected: &SimulationEvent, actual: &SimulationEvent, tolerance: f64) -> bool {
+    let close = |a: f64, b: f64| (a - b).abs() <= tolerance;
+    match (expected, actual) {
+        (&SimulationEvent::BallBall { t, i, j }, &SimulationEvent::BallBall { t: t2, i: i2, j: j2 }) =>
+            close(t, t2) && i == i2 && j == j2,
+        (&SimulationEvent::BallCloth { t, i }, &SimulationEvent::BallCloth { t: t2, i: i2 }) =>
+            close(t, t2) && i == i2,
+        (&SimulationEvent::BallCushion { t, i, ref cushion },
+         &SimulationEvent::BallCushion { t: t2, i: i2, cushion: ref cushion2 }) =>
+            close(t, t2) && i == i2 && cushion == cushion2,
+        (&SimulationEvent::BallPocket { t, i, pocket },
+         &SimulationEvent::BallPocket { t: t2, i: i2, pocket: pocket2 }) =>
+            close(t, t2) && i == i2 && pocket == pocket2,
+        _ => false,
+    }
+}
+
+pub fn compare(expected: &Trajectory, actual: &Trajectory, tolerances: &Tolerances) -> Diff {
+    let mut shape_mismatch = None;
+    if expected.samples.len() != actual.samples.len() {
+        shape_mismatch = Some(format!(
+            "{} samples expected, {} recorded", expected.samples.len(), actual.samples.len()));
+    }
+
+    let mut max_position_error: Option<PositionError> = None;
+    for (e, a) in expected.samples.iter().zip(actual.samples.iter()) {
+        if e.positions.len() != a.positions.len() {
+            shape_mismatch = Some(format!(
+                "{} balls expected, {} recorded", e.positions.len(), a.positions.len()));
+            break;
+        }
+        for (ball, (p, q)) in e.positions.iter().zip(a.positions.iter()).enumerate() {
+            let error = (p - q).abs().max();
+            if max_position_error.as_ref().map_or(true, |m| error > m.error) {
+                max_position_error = Some(PositionError { error: error, t: e.t, ball: ball });
+            }
+        }
+    }
+
+    let n_events = expected.events.len().max(actual.events.len());
+    let first_divergent_event = (0..n_events).find(|&k| {
+        match (expected.events.get(k), actual.events.get(k)) {
+            (Some(e), Some(a)) => !event_matches(e, a, tolerances.event_time),
+            _ => true,
+        }
+    }).map(|k| EventDivergence {
+        index: k,
+        expected: expected.events.get(k).cloned(),
+        actual: actual.events.get(k).cloned(),
+    });
+
+    Diff {
+        tolerances: tolerances.clone(),
+        max_position_error: max_position_error,
+        shape_mismatch: shape_mismatch,
+        first_divergent_event: first_divergent_event,
+    }
+}
+
+pub struct Reference {
+    // Also the name of the golden file, without the .txt.
+    pub name: &'static str,
+    pub scene: Scene,
+    pub shot: Shot,
+}
+
+// Between them the scenes exercise ball-to-ball contacts, cushions, pockets,
+// cloth friction, spin and jumps.
+pub fn catalogue() -> Vec<Reference> {
+    let scene = |text: &str| scene_file::parse(text).unwrap();
+    vec![
+        Reference {
+            name: "pool_corner_pot",
+            scene: scene("table pool\nball 0 0.67 0.035\nball 3 0.97 0.335"),
+            shot: Shot { ball: 0, u: JVector3::new(1., 1., 0.), omega: JVector3::zeros() },
+        },
+        Reference {
+            name: "pool_draw_off_cushion",
+            scene: scene("table pool\nball 0 -0.5 0.2\nball 5 0.5 0.2"),
+            shot: Shot {
+                ball: 0,
+                u: JVector3::new(2.5, 0., 0.),
+                omega: JVector3::new(0., -2.5 / 0.0286, 20.),
+            },
+        },
+        Reference {
+            name: "pool_jump",
+            scene: scene("table pool\nball 0 -0.9 0\nball 1 -0.26 0"),
+            shot: CueStrike { ball: 0, speed: 5., aim: 0., elevation: 45f64.to_radians() }.to_shot(),
+        },
+        Reference {
+            name: "snooker_break",
+            scene: snooker::default_scene(),
+            shot: Shot { ball: 0, u: JVector3::new(6., 0.12, 0.), omega: JVector3::zeros() },
+        },
+        Reference {
+            name: "snooker_napped_masse",
+            scene: scene("table snooker\ncloth snooker_napped\nball 0 -1 0"),
+            shot: Shot { ball: 0, u: JVector3::new(0.8, 0., 0.), omega: JVector3::new(300., 0., 1000.) },
+        },
+        Reference {
+            name: "carom_around_the_table",
+            scene: three_cushion::default_scene(),
+            shot: Shot { ball: 0, u: JVector3::new(2., 0.9, 0.), omega: JVector3::new(0., 0., -40.) },
+        },
+    ]
+}
+
+pub fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.txt", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use golden::{Tolerances, Trajectory, catalogue, compare, golden_path};
+    use jlib::{Cushion, SimulationEvent};
+
+    #[test]
+    fn test_golden_trajectories() {
+        let update = env::var("JUSTSHOOT_UPDATE_GOLDEN").is_ok();
+        let mut report = String::new();
+        for reference in catalogue() {
+            let actual = Trajectory::record(&reference.scene, &reference.shot);
+            let path = golden_path(reference.name);
+            if update {
+                actual.save(&path).unwrap();
+                continue;
+            }
+            let expected = match Trajectory::load(&path) {
+                Ok(expected) => expected,
+                Err(e) => panic!("{}: {}", path.display(), e),
+            };
+            let diff = compare(&expected, &actual, &Tolerances::standard());
+            if !diff.passes() {
+                report.push_str(&format!("{} changed:\n{}", reference.name, diff));
+            }
+        }
+        if !report.is_empty() {
+            panic!("golden trajectories differ\n{}", report);
+        }
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let reference = &catalogue()[0];
+        let trajectory = Trajectory::record(&reference.scene, &reference.shot);
+        assert!(!trajectory.events.is_empty());
+        assert_eq!(Trajectory::parse(&trajectory.to_text()).unwrap(), trajectory);
+    }
+
+    #[test]
+    fn test_diff_reports_what_moved() {
+        let reference = &catalogue()[0];
+        let expected = Trajectory::record(&reference.scene, &reference.shot);
+        let tolerances = Tolerances::standard();
+        assert!(compare(&expected, &expected, &tolerances).passes());
+
+        let mut actual = expected.clone();
+        actual.samples[10].positions[1].y += 0.002;
+        actual.events[1] = SimulationEvent::BallCushion { t: 0.5, i: 0, cushion: Cushion::Left };
+        let diff = compare(&expected, &actual, &tolerances);
+        assert!(!diff.passes());
+        let error = diff.max_position_error.as_ref().unwrap();
+        assert_eq!((error.ball, error.t), (1, expected.samples[10].t));
+        assert!((error.error - 0.002).abs() < 1e-12);
+        assert_eq!(diff.first_divergent_event.as_ref().unwrap().index, 1);
+
+        let text = format!("{}", diff);
+        assert!(text.contains("ball 1"));
+        assert!(text.contains("first divergent event #1"));
+
+        // A shorter log diverges where it ends.
+        actual = expected.clone();
+        actual.events.pop();
+        let divergence = compare(&expected, &actual, &tolerances).first_divergent_event.unwrap();
+        assert_eq!(divergence.index, expected.events.len() - 1);
+        assert!(divergence.actual.is_none());
+    }
+}
@@ -8,7 +8,8 @@ use geometry::{
     calc_norm_apprch_v,
     calc_interpolated_vector,
     calc_interpolated_quaternion,
-    rotate_point,
+    portable_sin_cos,
+    rotation_during,
 };
 
 pub mod consts {
@@ -21,8 +22,48 @@ pub mod consts {
 
     pub const BALL_BALL_REST: f64 = 0.95;
     pub const BALL_CLOTH_REST: f64 = 0.50;
-
-    pub const BALL_SPOT_RADIUS_FACTOR: f64 = 0.1;
+    pub const BALL_CUSHION_REST: f64 = 0.75;
+
+    // Coefficient of sliding friction between ball and cloth.
+    pub const BALL_CLOTH_FRICTION: f64 = 0.2;
+    // Coefficient of rolling resistance, and the deceleration in rad/s^2 of
+    // the spin about the vertical axis.
+    pub const BALL_CLOTH_ROLLING_RESISTANCE: f64 = 0.01;
+    pub const BALL_CLOTH_SPIN_DECAY: f64 = 10.;
+
+    // Playing surface of a 9 ft pool table, measured between cushion noses.
+    pub const POOL_TABLE_LENGTH: f64 = 2.54;
+    pub const POOL_TABLE_WIDTH: f64 = 1.27;
+    pub const POOL_CORNER_POCKET_RADIUS: f64 = 0.058;
+    pub const POOL_SIDE_POCKET_RADIUS: f64 = 0.064;
+    // Height of the cushion noses above the cloth.
+    pub const POOL_CUSHION_HEIGHT: f64 = 0.036;
+
+    pub const SNOOKER_BALL_RADIUS: f64 = 52.5 / 1000. / 2.;
+    pub const SNOOKER_BALL_WEIGHT: f64 = 142. / 1000.;
+    pub const SNOOKER_TABLE_LENGTH: f64 = 3.569;
+    pub const SNOOKER_TABLE_WIDTH: f64 = 1.778;
+    pub const SNOOKER_CORNER_POCKET_RADIUS: f64 = 0.043;
+    pub const SNOOKER_SIDE_POCKET_RADIUS: f64 = 0.050;
+    pub const SNOOKER_CUSHION_HEIGHT: f64 = 0.033;
+
+    pub const CAROM_BALL_RADIUS: f64 = 61.5 / 1000. / 2.;
+    pub const CAROM_BALL_WEIGHT: f64 = 210. / 1000.;
+    pub const CAROM_TABLE_LENGTH: f64 = 2.84;
+    pub const CAROM_TABLE_WIDTH: f64 = 1.42;
+    pub const CAROM_CUSHION_HEIGHT: f64 = 0.037;
+
+    // Below these speeds a ball on the cloth is considered to be at rest.
+    pub const REST_SPEED: f64 = 1e-3;
+    pub const REST_ANGULAR_SPEED: f64 = 1e-2;
+
+    // Timestep used by Simulator::predict when the live timestep is finer.
+    pub const PREDICTION_TS: f64 = 1e-3;
+
+    // Convergence criteria of the ball-to-ball contact solver. The tolerance
+    // is in m/s.
+    pub const CONTACT_SOLVER_MAX_ITERATIONS: usize = 1000;
+    pub const CONTACT_SOLVER_TOLERANCE: f64 = 1e-9;
 
     pub const ANGLE_MODICUM: f64 = 2. * ::std::f64::consts::PI / 10000.;
 
@@ -53,6 +94,167 @@ pub mod consts {
     */
 }
 
+#[derive(Clone)]
+pub struct Pocket {
+    // Only the x and y components are used.
+    pub pos: JVector3,
+    // A ball whose centre comes this close to pos drops into the pocket.
+    pub radius: f64,
+}
+
+#[derive(Clone)]
+pub struct Table {
+    // The playing surface is the rectangle bounded by the cushion noses. It
+    // is centred on the origin with its length along the x axis.
+    pub length: f64,
+    pub width: f64,
+    pub ball_cushion_rest: f64,
+    // Height of the cushion noses above the cloth. A ball whose lowest point
+    // is higher than that flies over the cushions.
+    pub cushion_height: f64,
+    pub pockets: Vec<Pocket>,
+}
+
+impl Table {
+    pub fn pool() -> Self {
+        Table::with_six_pockets(
+            consts::POOL_TABLE_LENGTH,
+            consts::POOL_TABLE_WIDTH,
+            consts::POOL_CORNER_POCKET_RADIUS,
+            consts::POOL_SIDE_POCKET_RADIUS,
+            consts::POOL_CUSHION_HEIGHT,
+        )
+    }
+
+    pub fn snooker() -> Self {
+        Table::with_six_pockets(
+            consts::SNOOKER_TABLE_LENGTH,
+            consts::SNOOKER_TABLE_WIDTH,
+            consts::SNOOKER_CORNER_POCKET_RADIUS,
+            consts::SNOOKER_SIDE_POCKET_RADIUS,
+            consts::SNOOKER_CUSHION_HEIGHT,
+        )
+    }
+
+    pub fn carom() -> Self {
+        Table {
+            length: consts::CAROM_TABLE_LENGTH,
+            width: consts::CAROM_TABLE_WIDTH,
+            ball_cushion_rest: consts::BALL_CUSHION_REST,
+            cushion_height: consts::CAROM_CUSHION_HEIGHT,
+            pockets: vec![],
+        }
+    }
+
+    fn with_six_pockets(
+        length: f64,
+        width: f64,
+        corner_radius: f64,
+        side_radius: f64,
+        cushion_height: f64,
+    ) -> Self {
+        let hl = length / 2.;
+        let hw = width / 2.;
+        let corner = |x: f64, y: f64| Pocket {
+            pos: JVector3::new(x, y, 0.),
+            radius: corner_radius,
+        };
+        let side = |y: f64| Pocket {
+            pos: JVector3::new(0., y, 0.),
+            radius: side_radius,
+        };
+        Table {
+            length: length,
+            width: width,
+            ball_cushion_rest: consts::BALL_CUSHION_REST,
+            cushion_height: cushion_height,
+            pockets: vec![
+                corner(-hl, -hw),
+                corner(-hl, hw),
+                side(-hw),
+                side(hw),
+                corner(hl, -hw),
+                corner(hl, hw),
+            ],
+        }
+    }
+
+    fn is_in_pocket_mouth(&self, pos: &JVector3, ball_radius: f64) -> bool {
+        // Cushions are cut away in front of the pockets, so a ball this close
+        // to a pocket does not bounce off them.
+        self.pockets.iter().any(|pocket| {
+            let d = JVector3::new(pos.x - pocket.pos.x, pos.y - pocket.pos.y, 0.);
+            d.norm() < pocket.radius + ball_radius
+        })
+    }
+}
+
+// Slopes are given as the horizontal direction in which the surface falls
+// away, with a length equal to the sine of the angle of the surface to the
+// horizontal. A ball on a slope s is pulled by -gravity * s.
+
+// A part of the table that is not level, such as a dip around a worn spot or
+// the edge of a slate that has settled.
+#[derive(Clone)]
+pub struct RollOff {
+    // Only the x and y components are used.
+    pub centre: JVector3,
+    pub radius: f64,
+    pub slope: JVector3,
+}
+
+// Cloth nap pushes slow balls along the direction of the nap, by up to
+// strength m/s^2 for a ball that has nearly stopped and by nothing from
+// max_speed upwards. Balls at rest are not moved.
+#[derive(Clone)]
+pub struct Nap {
+    pub direction: JUnitVector3,
+    pub strength: f64,
+    pub max_speed: f64,
+}
+
+#[derive(Clone)]
+pub struct TableConditions {
+    pub slope: JVector3,
+    pub nap: Option<Nap>,
+    pub roll_offs: Vec<RollOff>,
+}
+
+impl TableConditions {
+    // A perfect table.
+    pub fn level() -> Self {
+        TableConditions {
+            slope: JVector3::zeros(),
+            nap: None,
+            roll_offs: vec![],
+        }
+    }
+
+    // Horizontal acceleration of the centre of a ball on the cloth at pos
+    // moving with u. Friction with the cloth turns part of it into spin.
+    pub fn acceleration(&self, pos: &JVector3, u: &JVector3, gravity: f64) -> JVector3 {
+        let mut slope = self.slope;
+        for roll_off in self.roll_offs.iter() {
+            let d = JVector3::new(pos.x - roll_off.centre.x, pos.y - roll_off.centre.y, 0.);
+            if d.norm() < roll_off.radius {
+                slope += roll_off.slope;
+            }
+        }
+        let mut acceleration = -gravity * slope;
+
+        if let Some(ref nap) = self.nap {
+            let speed = JVector3::new(u.x, u.y, 0.).norm();
+            if speed >= consts::REST_SPEED && speed < nap.max_speed {
+                acceleration += nap.direction.unwrap() * nap.strength * (1. - speed / nap.max_speed);
+            }
+        }
+
+        acceleration.z = 0.;
+        acceleration
+    }
+}
+
+#[derive(Clone)]
 pub struct WorldConf {
     /* Common diameters:
         carom:   61.5 mm
@@ -60,17 +262,60 @@ pub struct WorldConf {
         snooker: 52.5 mm 
 
        Common weights:
+        carom:   210 g
         pool:    165 g
+        snooker: 142 g
     */
     pub ball_radius: f64,
     pub ball_weight: f64,
     pub ball_ball_rest: f64,
     pub ball_cloth_rest: f64,
-    pub ball_spot_poss: Vec<JUnitVector3>,
-    pub ball_spot_radius_factor: f64,
+    pub ball_cloth_friction: f64,
+    pub ball_cloth_rolling_resistance: f64,
+    pub ball_cloth_spin_decay: f64,
     pub gravity: f64,
+    // None means an unbounded cloth without cushions or pockets.
+    pub table: Option<Table>,
+    pub conditions: TableConditions,
 }
 
+impl WorldConf {
+    // The usual ball, cloth and cushion parameters for balls of the given
+    // size on the given table, which is level.
+    pub fn new(ball_radius: f64, ball_weight: f64, table: Option<Table>) -> Self {
+        WorldConf {
+            gravity: consts::GRAVITY,
+            ball_radius: ball_radius,
+            ball_weight: ball_weight,
+            ball_ball_rest: consts::BALL_BALL_REST,
+            ball_cloth_rest: consts::BALL_CLOTH_REST,
+            ball_cloth_friction: consts::BALL_CLOTH_FRICTION,
+            ball_cloth_rolling_resistance: consts::BALL_CLOTH_ROLLING_RESISTANCE,
+            ball_cloth_spin_decay: consts::BALL_CLOTH_SPIN_DECAY,
+            table: table,
+            conditions: TableConditions::level(),
+        }
+    }
+
+    pub fn pool() -> Self {
+        WorldConf::new(consts::POOL_BALL_RADIUS, consts::POOL_BALL_WEIGHT, Some(Table::pool()))
+    }
+
+    pub fn snooker() -> Self {
+        WorldConf::new(consts::SNOOKER_BALL_RADIUS, consts::SNOOKER_BALL_WEIGHT, Some(Table::snooker()))
+    }
+
+    pub fn carom() -> Self {
+        WorldConf::new(consts::CAROM_BALL_RADIUS, consts::CAROM_BALL_WEIGHT, Some(Table::carom()))
+    }
+
+    // About any axis through the centre, as for any solid sphere.
+    pub fn ball_moment_of_inertia(&self) -> f64 {
+        2. / 5. * self.ball_weight * self.ball_radius * self.ball_radius
+    }
+}
+
+#[derive(Clone)]
 pub struct DebugConf {
     should_print_collisions: bool,
 }
@@ -80,27 +325,57 @@ pub struct Ball {
     pub pos: JVector3,
     pub u: JVector3,
     pub rot: JUnitQuaternion,
-    // Using separate axis and angle for the rotation velocity. We cannot
-    // simply use a unit quaternion because it will not allow us to represent a
-    // rotation faster than 2*pi radians per second.
-    pub urot_axis: JUnitVector3,
-    pub urot_angle: f64,
+    // Angular velocity: the ball turns about the direction of omega at
+    // |omega| radians per second. We cannot simply use a unit quaternion
+    // because it will not allow us to represent a rotation faster than 2*pi
+    // radians per second.
+    pub omega: JVector3,
+    // A pocketed ball stays in the vector so that indices remain stable, but
+    // it no longer takes part in the simulation.
+    pub pocketed: bool,
 }
 
 impl Ball {
-    fn apply_velocities(&mut self, ts: f64) {
+    pub fn at_rest(pos: JVector3) -> Self {
+        Ball {
+            pos: pos,
+            u: JVector3::zeros(),
+            rot: JUnitQuaternion::identity(),
+            omega: JVector3::zeros(),
+            pocketed: false,
+        }
+    }
+
+    // Applies an impulse, in N s, at a point given relative to the centre of
+    // the ball. This is how contact models act on a ball: the impulse changes
+    // the velocity and, unless it points at the centre, the angular velocity.
+    pub fn apply_impulse(&mut self, impulse: &JVector3, offset: &JVector3, world_conf: &WorldConf) {
+        self.u += impulse / world_conf.ball_weight;
+        self.apply_angular_impulse(&offset.cross(impulse), world_conf);
+    }
+
+    // Angular impulse in N m s.
+    pub fn apply_angular_impulse(&mut self, angular_impulse: &JVector3, world_conf: &WorldConf) {
+        self.omega += angular_impulse / world_conf.ball_moment_of_inertia();
+    }
+
+    // Torque in N m, acting for ts seconds.
+    pub fn apply_torque(&mut self, torque: &JVector3, ts: f64, world_conf: &WorldConf) {
+        self.apply_angular_impulse(&(torque * ts), world_conf);
+    }
+
+    fn apply_velocities(&mut self, ts: f64, deterministic: bool) {
         // println!("apply_velocities: pos.z: {:?}, u.z: {:?}", self.pos.z, self.u.z);
         self.pos += self.u * ts;
-        let angle = self.urot_angle * ts;
-        let urot = JUnitQuaternion::from_axis_angle(
-            &self.urot_axis, angle);
-        self.rot = urot * self.rot;
+        if self.omega != JVector3::zeros() {
+            self.rot = rotation_during(&self.omega, ts, deterministic) * self.rot;
+        }
     }
 }
 
 pub struct SimulationState {
-    t: f64,
-    balls: Vec<Ball>,
+    pub t: f64,
+    pub balls: Vec<Ball>,
 }
 
 impl SimulationState {
@@ -119,81 +394,243 @@ pub struct SimulationStateSeq {
 impl SimulationStateSeq {
 
     pub fn calc_interpolated_at(&self, t: f64) -> SimulationState {
-        let sl = self.states.len();
+        interpolated_at(self.states.len(), |k| &self.states[k], t, "SimulationStateSeq")
+    }
 
-        if sl == 0 {
-            panic!("calc_interpolated_at called on empty SimulationStateSeq");
-        }
+}
 
-        if t <= self.states[0].t {
-            SimulationState {
-                t: t,
-                balls: self.states[0].balls.clone(),
-            }
+// The state at time t from the len states returned by get, in order of time.
+// Before the first state and after the last the balls stay where they were.
+// what names the caller in the panic message.
+fn interpolated_at<'a, F>(len: usize, get: F, t: f64, what: &str) -> SimulationState
+    where F: Fn(usize) -> &'a SimulationState
+{
+    if len == 0 {
+        panic!("calc_interpolated_at called on empty {}", what);
+    }
+
+    let (first, last) = (get(0), get(len - 1));
+    if t <= first.t {
+        SimulationState {
+            t: t,
+            balls: first.balls.clone(),
+        }
+    }
+    else if t >= last.t {
+        SimulationState {
+            t: t,
+            balls: last.balls.clone(),
         }
-        else if t >= self.states[sl-1].t {
-            SimulationState {
-                t: t,
-                balls: self.states[sl-1].balls.clone(),
+    }
+    else {
+        // At this point we have at least two states. If there was only one
+        // state then one of the two preceding conditions would have been
+        // true.
+        for k in 0..len - 1 {
+            let (state1, state2) = (get(k), get(k + 1));
+
+            if state1.t <= t && state2.t >= t {
+                return interpolate_states(state1, state2, t);
             }
         }
+
+        panic!("calc_interpolated_at finished without finding a result. This is unexpected.");
+    }
+}
+
+fn interpolate_states(state1: &SimulationState, state2: &SimulationState, t: f64) -> SimulationState {
+    let mut interpolated_balls = Vec::with_capacity(state1.balls.len());
+
+    for i in 0..state1.balls.len() {
+        interpolated_balls.push(Ball{
+            pos: calc_interpolated_vector(
+                &state1.balls[i].pos,
+                &state2.balls[i].pos,
+                state1.t,
+                state2.t,
+                t
+            ),
+            rot: calc_interpolated_quaternion(
+                &state1.balls[i].rot,
+                &state2.balls[i].rot,
+                state1.t,
+                state2.t,
+                t
+            ),
+            // It doesn't matter what we put in the velocities
+            // because this information will only be used for
+            // drawing. We are wasting some CPU cycles and some
+            // memory but it doesn't matter because
+            // interpolation is not in our critical path, I
+            // think.
+            u: JVector3::zeros(),
+            omega: JVector3::zeros(),
+            pocketed: state1.balls[i].pocketed,
+        });
+    }
+
+    SimulationState {
+        t: t,
+        balls: interpolated_balls,
+    }
+}
+
+// The latest states of a simulation, for callers that step far more often
+// than they look at the states, as the viewer does. The slots are allocated
+// once and overwritten in turn, oldest first, so pushing does not allocate.
+pub struct StateRing {
+    slots: Vec<SimulationState>,
+    // Index in slots of the oldest state.
+    start: usize,
+    len: usize,
+}
+
+impl StateRing {
+    pub fn new(capacity: usize, n_balls: usize) -> Self {
+        if capacity == 0 {
+            panic!("StateRing with zero capacity");
+        }
+        StateRing {
+            slots: (0..capacity)
+                .map(|_| SimulationState { t: 0., balls: Vec::with_capacity(n_balls) })
+                .collect(),
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    // Takes a snapshot of the simulator, replacing the oldest state once the
+    // ring is full.
+    pub fn push_from(&mut self, simulator: &Simulator) {
+        let capacity = self.slots.len();
+        let k = (self.start + self.len) % capacity;
+        simulator.snapshot_into(&mut self.slots[k]);
+        if self.len < capacity {
+            self.len += 1;
+        }
         else {
-            // At this point we have at least two states. If there was only one
-            // state then one of the two preceding conditions would have been
-            // true.
-            let mut interpolated_balls;
-
-            for i in 0..sl-1 {
-                let (state1, state2) = (&self.states[i], &self.states[i+1]);
-
-                if state1.t <= t && state2.t >= t {
-                    interpolated_balls = Vec::new();
-
-                    for i in 0..state1.balls.len() {
-                        interpolated_balls.push(Ball{
-                            pos: calc_interpolated_vector(
-                                &state1.balls[i].pos,
-                                &state2.balls[i].pos,
-                                state1.t,
-                                state2.t,
-                                t
-                            ),
-                            rot: calc_interpolated_quaternion(
-                                &state1.balls[i].rot,
-                                &state2.balls[i].rot,
-                                state1.t,
-                                state2.t,
-                                t
-                            ),
-                            // It doesn't matter what we put in the velocities
-                            // because this information will only be used for
-                            // drawing. We are wasting some CPU cycles and some
-                            // memory but it doesn't matter because
-                            // interpolation is not in our critical path, I
-                            // think.
-                            u: JVector3::zeros(),
-                            urot_axis: JUnitVector3::new_normalize(JVector3::new(1., 0., 0.)),
-                            urot_angle: 0.,
-                        });
-                    }
+            self.start = (self.start + 1) % capacity;
+        }
+    }
 
-                    return SimulationState {
-                        t: t,
-                        balls: interpolated_balls,
-                    }
-                }
-            }
+    // k counts from the oldest state.
+    pub fn get(&self, k: usize) -> &SimulationState {
+        if k >= self.len {
+            panic!("state {} of a StateRing holding {}", k, self.len);
+        }
+        &self.slots[(self.start + k) % self.slots.len()]
+    }
+
+    pub fn latest(&self) -> Option<&SimulationState> {
+        if self.len == 0 { None } else { Some(self.get(self.len - 1)) }
+    }
+
+    // As SimulationStateSeq::calc_interpolated_at, over the states held.
+    pub fn calc_interpolated_at(&self, t: f64) -> SimulationState {
+        interpolated_at(self.len, |k| self.get(k), t, "StateRing")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cushion {
+    Head,  // x = -length/2
+    Foot,  // x =  length/2
+    Right, // y = -width/2
+    Left,  // y =  width/2
+}
+
+// Events are recorded in Simulator::events in the order in which they happen.
+// Ball indices refer to Simulator::balls.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimulationEvent {
+    BallBall { t: f64, i: usize, j: usize },
+    BallCloth { t: f64, i: usize },
+    BallCushion { t: f64, i: usize, cushion: Cushion },
+    BallPocket { t: f64, i: usize, pocket: usize },
+}
+
+impl SimulationEvent {
+    pub fn t(&self) -> f64 {
+        match *self {
+            SimulationEvent::BallBall { t, .. } => t,
+            SimulationEvent::BallCloth { t, .. } => t,
+            SimulationEvent::BallCushion { t, .. } => t,
+            SimulationEvent::BallPocket { t, .. } => t,
+        }
+    }
+}
+
+// The state a cue strike leaves the struck ball in.
+#[derive(Clone)]
+pub struct Shot {
+    pub ball: usize,
+    pub u: JVector3,
+    pub omega: JVector3,
+}
+
+// A cue strike through the centre of the ball. aim is the direction of the
+// stroke on the table, measured from the x axis towards the y axis, and
+// elevation is the angle of the cue above the horizontal. An elevated cue
+// drives the ball down into the cloth, which throws it back up: that is how a
+// jump shot is played.
+#[derive(Clone)]
+pub struct CueStrike {
+    pub ball: usize,
+    pub speed: f64,
+    pub aim: f64,
+    pub elevation: f64,
+}
 
-            panic!("calc_interpolated_at finished without finding a result. This is unexpected.");
+impl CueStrike {
+    // Through portable_sin_cos, so that the shot is the same on every
+    // platform, as deterministic simulations need.
+    pub fn to_shot(&self) -> Shot {
+        let (elevation_sin, elevation_cos) = portable_sin_cos(self.elevation);
+        let (aim_sin, aim_cos) = portable_sin_cos(self.aim);
+        let horizontal = self.speed * elevation_cos;
+        Shot {
+            ball: self.ball,
+            u: JVector3::new(
+                horizontal * aim_cos,
+                horizontal * aim_sin,
+                -self.speed * elevation_sin,
+            ),
+            omega: JVector3::zeros(),
         }
     }
+}
 
+pub struct Prediction {
+    // The first ball-to-ball event of every ball that took part in one, in
+    // time order.
+    pub first_contacts: Vec<SimulationEvent>,
+    pub pocketed: Vec<usize>,
+    pub balls: Vec<Ball>,
+    pub events: Vec<SimulationEvent>,
 }
 
+#[derive(Clone)]
 struct BallBallCollisionEvent {
     i: usize, // index of ball_a
     j: usize, // index of ball_b
-    unit_normal: JVector3,
+    unit_normal: JVector3, // from ball_a to ball_b
 }
 
 struct BallClothCollisionEvent {
@@ -201,15 +638,47 @@ struct BallClothCollisionEvent {
     unit_normal: JVector3,
 }
 
+// Work space of a step. It is kept in the Simulator between steps, so that
+// once the vectors have grown to what the scene needs, stepping no longer
+// allocates.
+#[derive(Clone, Default)]
+struct StepBuffers {
+    // Broad phase: (grid cell, ball index) of every ball on the table, sorted,
+    // and the candidate pairs found from it.
+    cells: Vec<((i64, i64, i64), usize)>,
+    candidates: Vec<(usize, usize)>,
+    contacts: Vec<BallBallCollisionEvent>,
+    // Contact solver, see solve_ball_to_ball_impulses.
+    degree: Vec<usize>,
+    supported: Vec<usize>,
+    relax: Vec<f64>,
+    impulses: Vec<f64>,
+    support_impulses: Vec<f64>,
+    du: Vec<JVector3>,
+    changes: Vec<f64>,
+    support_changes: Vec<f64>,
+}
+
+#[derive(Clone)]
 pub struct Simulator {
     pub balls: Vec<Ball>,
     pub world_conf: WorldConf,
     pub debug_conf: DebugConf,
+    pub events: Vec<SimulationEvent>,
+    // In deterministic mode the simulation avoids every computation whose
+    // result may differ between platforms, and the hash of the state after
+    // each step is appended to state_hashes. Otherwise state_hashes stays
+    // empty. The simulator itself draws no random numbers; randomness such as
+    // the rack jitter comes from an Rng passed in by the caller, which has to
+    // be seeded for runs to repeat.
+    pub deterministic: bool,
+    pub state_hashes: Vec<u64>,
 // timestep. Keep it here to retain the option of altering its value
 // dynamically.
     ts: f64,
     pub t: f64,
     t_hard_limit: f64,
+    buffers: StepBuffers,
 }
 
 impl Simulator {
@@ -225,15 +694,111 @@ impl Simulator {
             debug_conf: DebugConf {
                 should_print_collisions: false,
             },
+            events: Vec::new(),
+            deterministic: false,
+            state_hashes: Vec::new(),
             ts: ts,
             t: 0.,
             t_hard_limit: 30.,
+            buffers: StepBuffers::default(),
+        }
+    }
+
+    pub fn apply_shot(&mut self, shot: &Shot) {
+        let ball = &mut self.balls[shot.ball];
+        ball.u = shot.u;
+        ball.omega = shot.omega;
+    }
+
+    // FNV-1a over the bits of the time and of the state of every ball. Two
+    // runs have followed the same path bit for bit exactly when their hashes
+    // agree at every step (up to hash collisions).
+    pub fn state_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        {
+            let mut feed = |x: f64| {
+                for byte in 0..8 {
+                    hash ^= (x.to_bits() >> (8 * byte)) & 0xff;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+            };
+            feed(self.t);
+            for ball in self.balls.iter() {
+                for x in ball.pos.iter()
+                    .chain(ball.u.iter())
+                    .chain(ball.rot.coords.iter())
+                    .chain(ball.omega.iter())
+                {
+                    feed(*x);
+                }
+                feed(if ball.pocketed { 1. } else { 0. });
+            }
+        }
+        hash
+    }
+
+    pub fn is_at_rest(&self) -> bool {
+        self.balls.iter().all(|ball| {
+            ball.pocketed || (
+                ball.pos.z <= self.world_conf.ball_radius
+                    &&
+                ball.u.norm() < consts::REST_SPEED
+                    &&
+                ball.omega.norm() < consts::REST_ANGULAR_SPEED
+            )
+        })
+    }
+
+    // Steps until every ball is at rest or t reaches t_end, whichever comes
+    // first.
+    pub fn run_until_rest(&mut self, t_end: f64) {
+        while self.t < t_end && !self.is_at_rest() {
+            self.step();
+        }
+    }
+
+    pub fn predict(&self, shot: &Shot, horizon: f64) -> Prediction {
+        // Runs the shot on a copy so that the live simulation is left alone.
+        // The copy uses a coarser timestep and stops as soon as everything
+        // has come to rest.
+        let mut simulator = self.clone();
+        simulator.ts = simulator.ts.max(consts::PREDICTION_TS);
+        simulator.debug_conf.should_print_collisions = false;
+        simulator.events.clear();
+        simulator.state_hashes.clear();
+        simulator.apply_shot(shot);
+
+        let t_end = simulator.t + horizon;
+        simulator.run_until_rest(t_end);
+
+        let mut first_contacts = Vec::new();
+        let mut contacted = vec![false; simulator.balls.len()];
+        let mut pocketed = Vec::new();
+        for event in simulator.events.iter() {
+            match *event {
+                SimulationEvent::BallBall { i, j, .. } => {
+                    if !contacted[i] || !contacted[j] {
+                        first_contacts.push(event.clone());
+                    }
+                    contacted[i] = true;
+                    contacted[j] = true;
+                }
+                SimulationEvent::BallPocket { i, .. } => pocketed.push(i),
+                _ => {}
+            }
+        }
+
+        Prediction {
+            first_contacts: first_contacts,
+            pocketed: pocketed,
+            balls: simulator.balls,
+            events: simulator.events,
         }
     }
 
     fn apply_gravity(&mut self) {
         for ball in self.balls.iter_mut() {
-            if ball.pos.z > self.world_conf.ball_radius {
+            if !ball.pocketed && ball.pos.z > self.world_conf.ball_radius {
                 let before = ball.u.z;
                 ball.u.z += self.world_conf.gravity * self.ts;
                 // println!("apply_gravity: ball.u.z before: {:?} ball.u.z after: {:?}",
@@ -244,7 +809,7 @@ impl Simulator {
 
     fn apply_ball_velocities(&mut self) {
         for ball in self.balls.iter_mut() {
-            ball.apply_velocities(self.ts);
+            ball.apply_velocities(self.ts, self.deterministic);
         }
     }
 
@@ -266,8 +831,28 @@ impl Simulator {
         SimulationStateSeq{states: states}
     }
 
+    // Steps and returns a copy of the new state. Callers that step at a high
+    // rate and only need some of the states should call step and take
+    // snapshots with snapshot_into or a StateRing instead, which do not
+    // allocate.
     pub fn progress(&mut self) -> SimulationState {
+        self.step();
+        SimulationState::from_simulator(self)
+    }
+
+    // Copies the current state into state, reusing its ball vector.
+    pub fn snapshot_into(&self, state: &mut SimulationState) {
+        state.t = self.t;
+        state.balls.clone_from(&self.balls);
+    }
+
+    // Advances the simulation by one timestep. Apart from the event log and
+    // the state hashes, which only grow, this allocates nothing once the
+    // first steps have sized the work space.
+    pub fn step(&mut self) {
         self.check_ball_to_ball_collisions();
+        self.check_ball_to_cushion_collisions();
+        self.check_ball_to_pocket();
         self.check_ball_to_cloth_collisions();
         // check_snap_to_cloth can only fullfil its purpose if it is called
         // after collisions to the cloth have applied changes to the velocities
@@ -282,48 +867,125 @@ impl Simulator {
         // println!("");
         self.t += self.ts;
 
-        SimulationState::from_simulator(self)
+        if self.deterministic {
+            let hash = self.state_hash();
+            self.state_hashes.push(hash);
+        }
     }
 
-    fn adjust_for_ball_to_ball_collisions(&mut self, coll_ev: &BallBallCollisionEvent) {
-        // The balls exchange the velocity vector components that coincide with
-        // the normal vector of the collision.
-
-        let comp_a: JVector3;
-        let comp_b: JVector3;
-
-        {
-            let ball_a = &self.balls[coll_ev.i];
-            let ball_b = &self.balls[coll_ev.j];
-
-            comp_a = ball_a.u.dot(&coll_ev.unit_normal) * coll_ev.unit_normal;
-            comp_b = ball_b.u.dot(&coll_ev.unit_normal) * coll_ev.unit_normal;
-
-            // println!("{:?}", comp_a);
-            // println!("{:?}", comp_b);
-            // println!("");
+    // Finds the impulse of every contact in buffers.contacts, and the impulse
+    // the cloth gives to each ball that is supported by it. They are left in
+    // buffers.impulses and, along z for the balls in buffers.supported, in
+    // buffers.support_impulses.
+    fn solve_ball_to_ball_impulses(&mut self) {
+        // Finds the impulse of every contact in two phases. The compression
+        // impulses are the smallest ones that leave no pair approaching. They
+        // are found with a projected Jacobi iteration: every impulse of an
+        // iteration is computed from the same velocities and they are all
+        // applied together, so the result does not depend on the order of the
+        // contacts. The restitution phase then adds ball_ball_rest times the
+        // compression impulse to every contact. Unlike asking each pair to
+        // separate at ball_ball_rest times its approach velocity, this never
+        // creates energy when several contacts are resolved at once.
+        //
+        // All balls have the same mass, so impulses are expressed as the
+        // velocity change they cause to each of the two balls.
+        //
+        // A ball on the cloth that is pushed down by another one, as when a
+        // jumping ball lands on it, is held up by the slate. The cloth takes
+        // part in the solve as one more contact for every such ball, one that
+        // only moves the ball. It shares the restitution of the ball-to-ball
+        // contact because the cloth merely backs the ball up; a bounce of the
+        // ball itself off the cloth is left to check_ball_to_cloth_collisions.
+        let r = self.world_conf.ball_radius;
+        let balls = &self.balls;
+        let StepBuffers {
+            ref contacts,
+            ref mut degree,
+            ref mut supported,
+            ref mut relax,
+            ref mut impulses,
+            ref mut support_impulses,
+            ref mut du,
+            ref mut changes,
+            ref mut support_changes,
+            ..
+        } = self.buffers;
+
+        degree.clear();
+        degree.resize(balls.len(), 0);
+        for contact in contacts.iter() {
+            degree[contact.i] += 1;
+            degree[contact.j] += 1;
         }
-
-        {
-            let ball_a = &mut self.balls[coll_ev.i];
-            ball_a.u -= comp_a;
-            ball_a.u += comp_b * self.world_conf.ball_ball_rest;
+        supported.clear();
+        supported.extend((0 .. balls.len()).filter(|&i| degree[i] > 0 && balls[i].pos.z <= r));
+        for &i in supported.iter() {
+            degree[i] += 1;
         }
-
-        {
-            let ball_b = &mut self.balls[coll_ev.j];
-            ball_b.u -= comp_b;
-            ball_b.u += comp_a * self.world_conf.ball_ball_rest;
+        // Under-relaxation keeps the iteration from overshooting when a ball
+        // takes part in several contacts.
+        relax.clear();
+        relax.extend(contacts.iter().map(|c| 1. / degree[c.i].max(degree[c.j]) as f64));
+
+        impulses.clear();
+        impulses.resize(contacts.len(), 0.);
+        support_impulses.clear();
+        support_impulses.resize(supported.len(), 0.);
+        du.clear();
+        du.resize(balls.len(), JVector3::zeros());
+        for _ in 0 .. consts::CONTACT_SOLVER_MAX_ITERATIONS {
+            changes.clear();
+            changes.extend(contacts.iter().enumerate().map(|(k, c)| {
+                let ua = balls[c.i].u + du[c.i];
+                let ub = balls[c.j].u + du[c.j];
+                let apprch_v = calc_norm_apprch_v(&balls[c.i].pos, &balls[c.j].pos, &ua, &ub);
+                // An impulse of x reduces the approach velocity by 2x.
+                let wanted = impulses[k] + relax[k] * apprch_v / 2.;
+                wanted.max(0.) - impulses[k]
+            }));
+
+            support_changes.clear();
+            support_changes.extend(supported.iter().enumerate().map(|(k, &i)| {
+                // An impulse of x reduces the speed into the cloth by x.
+                let into_cloth = -(balls[i].u.z + du[i].z);
+                let wanted = support_impulses[k] + into_cloth / degree[i] as f64;
+                wanted.max(0.) - support_impulses[k]
+            }));
+
+            let mut max_change: f64 = 0.;
+            for (k, c) in contacts.iter().enumerate() {
+                impulses[k] += changes[k];
+                du[c.i] -= changes[k] * c.unit_normal;
+                du[c.j] += changes[k] * c.unit_normal;
+                max_change = max_change.max(changes[k].abs());
+            }
+            for (k, &i) in supported.iter().enumerate() {
+                support_impulses[k] += support_changes[k];
+                du[i].z += support_changes[k];
+                max_change = max_change.max(support_changes[k].abs());
+            }
+            if max_change < consts::CONTACT_SOLVER_TOLERANCE {
+                break;
+            }
         }
 
+        let rest = self.world_conf.ball_ball_rest;
+        for impulse in impulses.iter_mut().chain(support_impulses.iter_mut()) {
+            *impulse *= 1. + rest;
+        }
     }
 
     fn adjust_for_ball_to_cloth_collisions(&mut self, coll_ev: &BallClothCollisionEvent) {
+        // The impulse takes the velocity into the cloth to ball_cloth_rest
+        // times that velocity out of it. It acts at the contact point, along
+        // the normal, so it does not change the spin.
+        let world_conf = &self.world_conf;
         let ball = &mut self.balls[coll_ev.i];
         let comp = ball.u.dot(&coll_ev.unit_normal) * coll_ev.unit_normal;
         let before = ball.u.z;
-        ball.u -= comp;
-        ball.u -= comp * self.world_conf.ball_cloth_rest;
+        let impulse = -comp * (1. + world_conf.ball_cloth_rest) * world_conf.ball_weight;
+        ball.apply_impulse(&impulse, &(-coll_ev.unit_normal * world_conf.ball_radius), world_conf);
         // println!("adjust_for_ball_to_cloth_collisions: ball.u.z before: {:?} ball.u.z after: {:?}",
         //          before, ball.u.z);
     }
@@ -385,62 +1047,119 @@ impl Simulator {
         }
     }
 
-    fn check_ball_to_ball_collisions(&mut self) {
-        // We only care about collisions with balls that are approaching each
-        // other. Non-approaching balls colliding is an artifact of the
-        // simulation process, which allows balls to penetrate each other.
-
-        let n_balls = self.balls.len();
-
-        for i in 0 .. n_balls-1 {
-            for j in i+1 .. n_balls {
+    fn find_ball_to_ball_candidates(&mut self) {
+        // Broad phase: a uniform grid whose cells are one ball diameter wide.
+        // Two balls can only touch if they are in the same or in neighbouring
+        // cells, so instead of all pairs only the balls in the 27 cells
+        // around each ball are looked at. The pairs are left in
+        // buffers.candidates with i < j and in the same order as an
+        // all-pairs loop would produce them.
+        //
+        // The grid is kept as a sorted list of (cell, ball) rather than a
+        // map, so that the balls of a cell are found by binary search and
+        // nothing has to be allocated on each step.
+        let cell_size = 2. * self.world_conf.ball_radius;
+        let cell_of = |pos: &JVector3| (
+            (pos.x / cell_size).floor() as i64,
+            (pos.y / cell_size).floor() as i64,
+            (pos.z / cell_size).floor() as i64,
+        );
 
-                let mut coll_ev_maybe: Option<BallBallCollisionEvent> = None;
-                {
-                    let ball_a = &self.balls[i];
-                    let ball_b = &self.balls[j];
-
-                    let norm_apprch_v = calc_norm_apprch_v(
-                        &ball_a.pos,
-                        &ball_b.pos,
-                        &ball_a.u,
-                        &ball_b.u,
-                    );
-
-                    let r = ball_b.pos - ball_a.pos;
-                    let r_norm = r.norm();
-
-                    // println!("a_pos: {:?}", ball_a.pos);
-                    // println!("b_pos: {:?}", ball_b.pos);
-                    // println!("{:?}", r_norm);
-                    // println!("");
-
-                    if r_norm > 0. {
-                        // Avoids the division-by-zero case where balls are in the
-                        // same place.
-                        if norm_apprch_v > 0. {
-                            // Balls are approaching.
-                            if r_norm <= 2. * self.world_conf.ball_radius {
-                                // Balls are colliding.
-                                coll_ev_maybe = Some(
-                                    BallBallCollisionEvent {
-                                        i: i,
-                                        j: j,
-                                        unit_normal: r / r_norm,
-                                    }
-                                );
+        let cells = &mut self.buffers.cells;
+        cells.clear();
+        for (i, ball) in self.balls.iter().enumerate() {
+            if !ball.pocketed {
+                cells.push((cell_of(&ball.pos), i));
+            }
+        }
+        cells.sort_unstable();
+
+        let candidates = &mut self.buffers.candidates;
+        candidates.clear();
+        for &((cx, cy, cz), i) in cells.iter() {
+            for dx in -1 .. 2 {
+                for dy in -1 .. 2 {
+                    for dz in -1 .. 2 {
+                        let cell = (cx + dx, cy + dy, cz + dz);
+                        let mut k = match cells.binary_search(&(cell, 0)) {
+                            Ok(k) | Err(k) => k,
+                        };
+                        while k < cells.len() && cells[k].0 == cell {
+                            if cells[k].1 > i {
+                                candidates.push((i, cells[k].1));
                             }
+                            k += 1;
                         }
                     }
                 }
+            }
+        }
+        // The pairs are distinct, so an unstable sort gives the same order
+        // and, unlike sort, needs no buffer.
+        candidates.sort_unstable();
+    }
 
-                if let Some(coll_ev) = coll_ev_maybe {
-                    self.adjust_for_ball_to_ball_collisions(&coll_ev);
-                }
-
+    fn check_ball_to_ball_collisions(&mut self) {
+        // All balls that are touching are resolved together, see
+        // solve_ball_to_ball_impulses. Resolving them pair by pair in index
+        // order made the outcome depend on the order of the balls whenever a
+        // ball was touching several others, as when a rack is broken.
+        //
+        // Pairs that are touching but not approaching are included because
+        // the impulses of the other contacts can make them approach. Pairs
+        // that are still separating after the solve get no impulse;
+        // non-approaching balls overlapping is an artifact of the simulation
+        // process, which allows balls to penetrate each other.
+
+        self.find_ball_to_ball_candidates();
+        self.buffers.contacts.clear();
+
+        for &(i, j) in self.buffers.candidates.iter() {
+            let r = self.balls[j].pos - self.balls[i].pos;
+            let r_norm = r.norm();
+
+            // r_norm > 0. avoids the division-by-zero case where balls are in
+            // the same place.
+            if r_norm > 0. && r_norm <= 2. * self.world_conf.ball_radius {
+                self.buffers.contacts.push(BallBallCollisionEvent {
+                    i: i,
+                    j: j,
+                    unit_normal: r / r_norm,
+                });
             }
         }
 
+        if self.buffers.contacts.is_empty() {
+            return;
+        }
+
+        // The solver works with impulses per unit mass, that is velocity
+        // changes. They act at the contact points along the normals and
+        // leave the spins alone.
+        self.solve_ball_to_ball_impulses();
+        let buffers = &self.buffers;
+        let m = self.world_conf.ball_weight;
+        let r = self.world_conf.ball_radius;
+        for (contact, &impulse) in buffers.contacts.iter().zip(buffers.impulses.iter()) {
+            if impulse <= 0. {
+                continue;
+            }
+            if self.debug_conf.should_print_collisions {
+                println!("Ball-to-ball collision. Balls: {:?} {:?}", contact.i, contact.j);
+            }
+            self.events.push(SimulationEvent::BallBall {
+                t: self.t,
+                i: contact.i,
+                j: contact.j,
+            });
+            let normal_impulse = contact.unit_normal * (impulse * m);
+            self.balls[contact.i].apply_impulse(&-normal_impulse, &(contact.unit_normal * r), &self.world_conf);
+            self.balls[contact.j].apply_impulse(&normal_impulse, &(-contact.unit_normal * r), &self.world_conf);
+        }
+        for (&i, &impulse) in buffers.supported.iter().zip(buffers.support_impulses.iter()) {
+            let support_impulse = JVector3::new(0., 0., impulse * m);
+            self.balls[i].apply_impulse(&support_impulse, &JVector3::new(0., 0., -r), &self.world_conf);
+        }
     }
 
     fn check_ball_to_cloth_collisions(&mut self) {
@@ -450,7 +1169,7 @@ impl Simulator {
             {
                 let ball = &self.balls[i];
 
-                if ball.u.z <= 0. {
+                if !ball.pocketed && ball.u.z <= 0. {
                     // Ball is approaching the cloth.
                     if ball.pos.z <= self.world_conf.ball_radius {
                         // Ball is colliding with the cloth.
@@ -469,37 +1188,209 @@ impl Simulator {
                 if self.debug_conf.should_print_collisions {
                     println!("Ball-to-cloth collision. Ball: {:?}", coll_ev.i);
                 }
+                // A ball resting on the cloth collides with it on every step;
+                // only actual impacts are worth recording.
+                let speed_into_cloth = -self.balls[i].u.z;
+                if speed_into_cloth > 0. {
+                    self.events.push(SimulationEvent::BallCloth { t: self.t, i: i });
+                }
                 self.adjust_for_ball_to_cloth_collisions(&coll_ev);
-                self.adjust_ball_for_spin(i, &coll_ev.unit_normal);
+
+                // The normal impulse of an impact takes the ball from
+                // speed_into_cloth to ball_cloth_rest times that speed away
+                // from the cloth. A ball lying on the cloth is held up
+                // against gravity for the duration of the step, and also
+                // feels the table conditions and the rolling resistance.
+                if speed_into_cloth > 0. {
+                    let normal_impulse = (1. + self.world_conf.ball_cloth_rest) * speed_into_cloth;
+                    self.apply_cloth_friction(i, normal_impulse);
+                }
+                else {
+                    {
+                        let ball = &mut self.balls[i];
+                        ball.u += self.world_conf.conditions.acceleration(
+                            &ball.pos, &ball.u, self.world_conf.gravity) * self.ts;
+                    }
+                    let normal_impulse = -self.world_conf.gravity * self.ts;
+                    self.apply_cloth_friction(i, normal_impulse);
+                    self.apply_rolling_resistance(i);
+                }
+            }
+        }
+    }
+
+    fn check_ball_to_cushion_collisions(&mut self) {
+        let world_conf = &self.world_conf;
+        let table = match world_conf.table {
+            Some(ref table) => table,
+            None => return,
+        };
+        let r = world_conf.ball_radius;
+        let hl = table.length / 2.;
+        let hw = table.width / 2.;
+        // The impulse of a cushion, at the contact point and along the
+        // normal, reverses the velocity into the cushion and scales it by
+        // ball_cushion_rest.
+        let rebound = |u: f64| -u * (1. + table.ball_cushion_rest) * world_conf.ball_weight;
+
+        for (i, ball) in self.balls.iter_mut().enumerate() {
+            // A jumping ball flies over the cushions once its lowest point
+            // is above the noses. It then leaves the table and nothing stops
+            // it; there are no rails in the model.
+            if ball.pocketed || table.is_in_pocket_mouth(&ball.pos, r) || ball.pos.z - r > table.cushion_height {
+                continue;
+            }
+
+            // Only balls moving towards a cushion bounce off it, for the same
+            // reason as in check_ball_to_ball_collisions.
+            let mut cushion_maybe = None;
+            if ball.pos.x - r <= -hl && ball.u.x < 0. {
+                let impulse = JVector3::new(rebound(ball.u.x), 0., 0.);
+                ball.apply_impulse(&impulse, &JVector3::new(-r, 0., 0.), world_conf);
+                cushion_maybe = Some(Cushion::Head);
+            }
+            else if ball.pos.x + r >= hl && ball.u.x > 0. {
+                let impulse = JVector3::new(rebound(ball.u.x), 0., 0.);
+                ball.apply_impulse(&impulse, &JVector3::new(r, 0., 0.), world_conf);
+                cushion_maybe = Some(Cushion::Foot);
+            }
+            if let Some(cushion) = cushion_maybe {
+                self.events.push(SimulationEvent::BallCushion { t: self.t, i: i, cushion: cushion });
+            }
+
+            let mut cushion_maybe = None;
+            if ball.pos.y - r <= -hw && ball.u.y < 0. {
+                let impulse = JVector3::new(0., rebound(ball.u.y), 0.);
+                ball.apply_impulse(&impulse, &JVector3::new(0., -r, 0.), world_conf);
+                cushion_maybe = Some(Cushion::Right);
+            }
+            else if ball.pos.y + r >= hw && ball.u.y > 0. {
+                let impulse = JVector3::new(0., rebound(ball.u.y), 0.);
+                ball.apply_impulse(&impulse, &JVector3::new(0., r, 0.), world_conf);
+                cushion_maybe = Some(Cushion::Left);
+            }
+            if let Some(cushion) = cushion_maybe {
+                self.events.push(SimulationEvent::BallCushion { t: self.t, i: i, cushion: cushion });
             }
         }
     }
 
-    fn adjust_ball_for_spin(&mut self, ball_i: usize, unit_normal: &JVector3) {
+    fn check_ball_to_pocket(&mut self) {
+        let table = match self.world_conf.table {
+            Some(ref table) => table,
+            None => return,
+        };
+
+        for (i, ball) in self.balls.iter_mut().enumerate() {
+            // A ball in the air flies over the pocket and only drops if it
+            // comes down inside it.
+            if ball.pocketed || ball.pos.z > self.world_conf.ball_radius {
+                continue;
+            }
+            for (pocket_i, pocket) in table.pockets.iter().enumerate() {
+                let d = JVector3::new(ball.pos.x - pocket.pos.x, ball.pos.y - pocket.pos.y, 0.);
+                if d.norm() <= pocket.radius {
+                    ball.pocketed = true;
+                    ball.u = JVector3::zeros();
+                    ball.omega = JVector3::zeros();
+                    self.events.push(SimulationEvent::BallPocket { t: self.t, i: i, pocket: pocket_i });
+                    break;
+                }
+            }
+        }
+    }
+
+    fn apply_rolling_resistance(&mut self, ball_i: usize) {
+        // The cloth gives way a little in front of a rolling ball, which
+        // slows it down at ball_cloth_rolling_resistance * g. The cloth
+        // pushes back at the contact point and, as the normal force acts a
+        // little ahead of that point in the dent, also has a moment against
+        // the horizontal spin. With J the push back, the moment is 7/5 R |J|,
+        // which together with the moment of J itself slows the spin of a
+        // rolling ball by |J| / (m R), so that it keeps rolling. The moment
+        // never does more than stop the horizontal spin, so a sliding ball,
+        // with draw for instance, only loses energy. Spin about the vertical
+        // axis, which friction does not touch, dies away at
+        // ball_cloth_spin_decay under a torque about z.
+        let world_conf = &self.world_conf;
+        let r = world_conf.ball_radius;
+        let ts = self.ts;
         let ball = &mut self.balls[ball_i];
-        let a_dot_n = ball.urot_axis.dot(unit_normal);
-        if a_dot_n > 0. {
-            let k = self.world_conf.ball_radius / a_dot_n;
-            let x = k * ball.urot_axis.unwrap();
-            let rotated_center = rotate_point(
-                &(ball.pos - x),
-                &JUnitQuaternion::from_axis_angle(
-                    &JUnitVector3::new_normalize(JVector3::new(unit_normal.x, unit_normal.y, unit_normal.z)),
-                    ball.urot_angle * a_dot_n * 0.0001
-                ),
-            ) + x;
-            ball.pos = rotated_center;
+
+        let horizontal = JVector3::new(ball.u.x, ball.u.y, 0.);
+        let speed = horizontal.norm();
+        if speed > 0. {
+            let max_du = world_conf.ball_cloth_rolling_resistance * -world_conf.gravity * ts;
+            let impulse = -horizontal / speed * (max_du.min(speed) * world_conf.ball_weight);
+            ball.apply_impulse(&impulse, &JVector3::new(0., 0., -r), world_conf);
+
+            let spin = JVector3::new(ball.omega.x, ball.omega.y, 0.);
+            let spin_norm = spin.norm();
+            if spin_norm > 0. {
+                let max_moment = 7. / 5. * r * impulse.norm();
+                let stopping_moment = spin_norm * world_conf.ball_moment_of_inertia();
+                let normal_force_moment = -spin / spin_norm * max_moment.min(stopping_moment);
+                ball.apply_angular_impulse(&normal_force_moment, world_conf);
+            }
         }
+
+        let max_dw = world_conf.ball_cloth_spin_decay * ts;
+        let dw = -ball.omega.z.signum() * max_dw.min(ball.omega.z.abs());
+        let torque = JVector3::new(0., 0., dw * world_conf.ball_moment_of_inertia() / ts);
+        ball.apply_torque(&torque, ts, world_conf);
+    }
+
+    fn apply_cloth_friction(&mut self, ball_i: usize, normal_impulse: f64) {
+        // Coulomb friction at the contact point of a ball with the cloth. The
+        // friction impulse acts against the slip of the contact point and is
+        // at most ball_cloth_friction times the normal impulse, which comes
+        // from the impact when the ball bounces and from gravity when it
+        // lies on the cloth. Both impulses are per unit mass, i.e. velocity
+        // changes.
+        //
+        // This is how spin turns into a change of the linear velocity. A ball
+        // landing with topspin is thrown forward and one with backspin is
+        // held back, while sidespin about a vertical axis does not move the
+        // contact point and changes nothing. On the cloth the friction slows
+        // the ball down or speeds it up until it rolls without slipping. When
+        // the spin axis is tilted out of the plane that contains the
+        // direction of travel, as after a massé stroke, the slip has a
+        // sideways component and the friction curves the path of the ball.
+        //
+        // The contact point is at r = (0, 0, -R) from the centre and its
+        // velocity is u + omega x r. A horizontal impulse J there changes the
+        // slip by J (1/m + R^2/I), which for a solid sphere is 7/2 J/m. The
+        // impulse is capped so that it stops the slip without reversing it.
+        let world_conf = &self.world_conf;
+        let r = world_conf.ball_radius;
+        let m = world_conf.ball_weight;
+        let contact = JVector3::new(0., 0., -r);
+        let ball = &mut self.balls[ball_i];
+        let mut slip = ball.u + ball.omega.cross(&contact);
+        slip.z = 0.;
+        let slip_norm = slip.norm();
+        if slip_norm == 0. {
+            return;
+        }
+
+        let max_impulse = world_conf.ball_cloth_friction * m * normal_impulse;
+        let stopping_impulse = slip_norm / (1. / m + r * r / world_conf.ball_moment_of_inertia());
+        let impulse = -slip / slip_norm * max_impulse.min(stopping_impulse);
+        ball.apply_impulse(&impulse, &contact, world_conf);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use consts;
-    use Ball;
-    use Simulator;
-    use WorldConf;
-    use geometry::{JVector3, JUnitQuaternion};
+    use rand::{Rng, SeedableRng, prng::XorShiftRng};
+
+    use jlib::{
+        consts, Ball, CueStrike, Nap, RollOff, Shot, SimulationEvent, SimulationState, SimulationStateSeq,
+        Simulator, StateRing, TableConditions, WorldConf,
+    };
+    use fixtures;
+    use rules::rack::{self, RackConf};
+    use geometry::{JVector3, JUnitVector3, JUnitQuaternion};
 
     #[test]
     fn test_quaternions() {
@@ -510,45 +1401,983 @@ mod tests {
         let q2 = JUnitQuaternion::from_euler_angles(0., 0., 2. * consts::PI);
     }
 
-    fn setup_test_check_ball_to_ball_collisions() -> Simulator {
-        let world_conf = WorldConf {
-            ball_radius: consts::POOL_BALL_RADIUS,
-            ball_weight: consts::POOL_BALL_WEIGHT,
+    // No cushions, and a cloth without rolling resistance or spin decay, so
+    // that the effect under test is the only thing acting on the ball.
+    fn open_cloth_world_conf() -> WorldConf {
+        let mut world_conf = WorldConf::pool();
+        world_conf.table = None;
+        world_conf.ball_cloth_rolling_resistance = 0.;
+        world_conf.ball_cloth_spin_decay = 0.;
+        world_conf
+    }
+
+    fn ball_at_rest(x: f64, y: f64) -> Ball {
+        Ball::at_rest(JVector3::new(x, y, consts::POOL_BALL_RADIUS))
+    }
+
+    fn shot_towards_foot_left_corner() -> (Simulator, Shot) {
+        let (simulator, _) = fixtures::corner_pot().into_simulator(1e-4);
+        let shot = Shot {
+            ball: 0,
+            u: JVector3::new(1., 1., 0.),
+            omega: JVector3::zeros(),
         };
+        (simulator, shot)
+    }
 
-        let balls = vec![
-            Ball {
-                pos: JVector3::new(0., 0., 0.),
-                u: JVector3::new(1., 0.001, 0.),
-                rot: JUnitQuaternion::identity(),
-                urot: JUnitQuaternion::identity(),
-            },
-            Ball {
-                pos: JVector3::new(1., 0., 0.),
-                u: JVector3::new(0., 0., 0.),
-                rot: JUnitQuaternion::identity(),
-                urot: JUnitQuaternion::identity(),
-            },
-        ];
+    #[test]
+    fn test_predict_leaves_simulator_untouched() {
+        let (simulator, shot) = shot_towards_foot_left_corner();
+        simulator.predict(&shot, 2.);
+
+        assert_eq!(simulator.t, 0.);
+        assert!(simulator.events.is_empty());
+        assert_eq!(simulator.balls[0].u, JVector3::zeros());
+        assert!(!simulator.balls[1].pocketed);
+    }
 
-        let simulator = Simulator::new(
-            balls,
-            world_conf,
-            1e-3,
-        );
+    #[test]
+    fn test_predict_reports_contacts_and_pocketed_balls() {
+        let (simulator, shot) = shot_towards_foot_left_corner();
+        let prediction = simulator.predict(&shot, 2.);
+
+        match prediction.first_contacts[0] {
+            SimulationEvent::BallBall { i, j, .. } => assert_eq!((i, j), (0, 1)),
+            ref other => panic!("unexpected first contact {:?}", other),
+        }
+        assert_eq!(prediction.pocketed[0], 1);
+        assert!(prediction.balls[1].pocketed);
+        assert!(prediction.events.iter().any(|e| match *e {
+            SimulationEvent::BallPocket { i: 1, pocket: 5, .. } => true,
+            _ => false,
+        }));
+    }
 
+    #[test]
+    fn test_deterministic_replays_have_equal_hashes() {
+        let replay = || {
+            let mut simulator = Simulator::new(centred_break(0.0001), WorldConf::pool(), 1e-4);
+            simulator.deterministic = true;
+            for _ in 0..3000 {
+                simulator.progress();
+            }
+            simulator.state_hashes
+        };
+        let hashes = replay();
+        assert_eq!(hashes.len(), 3000);
+        assert_eq!(hashes, replay());
+
+        // The hash does see a change in the last bit of a single coordinate.
+        let mut simulator = Simulator::new(centred_break(0.0001), WorldConf::pool(), 1e-4);
+        let hash = simulator.state_hash();
+        let x = simulator.balls[5].pos.x;
+        simulator.balls[5].pos.x = f64::from_bits(x.to_bits() + 1);
+        assert!(simulator.state_hash() != hash);
+    }
+
+    fn centred_break(gap: f64) -> Vec<Ball> {
+        let mut conf = RackConf::pool();
+        conf.gap = gap;
+        let mut balls = vec![ball_at_rest(-consts::POOL_TABLE_LENGTH / 4., 0.)];
+        balls[0].u = JVector3::new(10., 0., 0.);
+        balls.extend(rack::snooker(&conf).balls);
+        balls
+    }
+
+    fn run(balls: Vec<Ball>, steps: usize) -> Simulator {
+        let mut simulator = Simulator::new(balls, WorldConf::pool(), 1e-4);
+        for _ in 0..steps {
+            simulator.progress();
+        }
         simulator
     }
 
     #[test]
-    fn test_check_ball_to_ball_collisions() {
-        let mut simulator = setup_test_check_ball_to_ball_collisions();
+    fn test_centred_break_is_mirror_symmetric() {
+        for &gap in [0., rack::DEFAULT_GAP].iter() {
+            let simulator = run(centred_break(gap), 2000);
+            for ball in simulator.balls.iter() {
+                let mirrored = simulator.balls.iter().any(|other| {
+                    (other.pos.x - ball.pos.x).abs() < 1e-9
+                        && (other.pos.y + ball.pos.y).abs() < 1e-9
+                        && (other.u.x - ball.u.x).abs() < 1e-9
+                        && (other.u.y + ball.u.y).abs() < 1e-9
+                });
+                assert!(mirrored, "no mirror image for ball at {:?}", ball.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_break_does_not_depend_on_ball_order() {
+        let balls = centred_break(0.);
+        let mut reversed = balls.clone();
+        reversed.reverse();
+
+        let simulator = run(balls, 2000);
+        let simulator_reversed = run(reversed, 2000);
+        let n = simulator.balls.len();
+        for i in 0..n {
+            let a = &simulator.balls[i];
+            let b = &simulator_reversed.balls[n - 1 - i];
+            assert!((a.pos - b.pos).norm() < 1e-9);
+            assert!((a.u - b.u).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_break_conserves_momentum_with_elastic_balls() {
+        let mut world_conf = WorldConf::pool();
+        world_conf.ball_ball_rest = 1.;
+        world_conf.ball_cloth_friction = 0.;
+        world_conf.ball_cloth_rolling_resistance = 0.;
+        world_conf.table = None;
+        let balls = centred_break(0.);
+        let momentum_before: JVector3 = balls.iter().map(|ball| ball.u).sum();
+
+        let mut simulator = Simulator::new(balls, world_conf, 1e-4);
+        for _ in 0..2000 {
+            simulator.progress();
+        }
+        let momentum_after: JVector3 = simulator.balls.iter().map(|ball| ball.u).sum();
+        let energy_after: f64 = simulator.balls.iter().map(|ball| ball.u.norm_squared()).sum();
+
+        assert!((momentum_after - momentum_before).norm() < 1e-9);
+        assert!(energy_after <= 100. + 1e-9);
+        assert!(simulator.balls.iter().filter(|ball| ball.u.norm() > 0.).count() > 10);
+    }
+
+    #[test]
+    fn test_broad_phase_finds_every_touching_pair() {
+        // Balls on a jittered grid, close enough for many of them to touch,
+        // some of them in the air.
+        let r = consts::POOL_BALL_RADIUS;
+        let mut balls = Vec::new();
+        for k in 0..400 {
+            let (x, y) = ((k % 20) as f64, (k / 20) as f64);
+            let wobble = ((k * 7919) % 13) as f64 / 13. - 0.5;
+            balls.push(ball_at_rest(x * 1.9 * r + wobble * r, y * 2.05 * r - wobble * r));
+            balls[k].pos.z += if k % 3 == 0 { wobble.abs() * r } else { 0. };
+        }
+        let mut simulator = Simulator::new(balls, WorldConf::pool(), 1e-4);
+        simulator.find_ball_to_ball_candidates();
+        let candidates = simulator.buffers.candidates.clone();
+
+        let touching = |&(i, j): &(usize, usize)| {
+            (simulator.balls[i].pos - simulator.balls[j].pos).norm() <= 2. * r
+        };
+        let mut all_pairs = Vec::new();
+        for i in 0..simulator.balls.len() {
+            for j in i+1..simulator.balls.len() {
+                all_pairs.push((i, j));
+            }
+        }
+        let expected: Vec<(usize, usize)> = all_pairs.into_iter().filter(|p| touching(p)).collect();
+        let found: Vec<(usize, usize)> = candidates.into_iter().filter(|p| touching(p)).collect();
+
+        assert!(expected.len() > 100);
+        assert_eq!(found, expected);
+    }
+
+    fn same_balls(a: &[Ball], b: &[Ball]) -> bool {
+        a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| {
+            a.pos == b.pos && a.u == b.u && a.rot == b.rot && a.omega == b.omega && a.pocketed == b.pocketed
+        })
+    }
+
+    #[test]
+    fn test_snapshots_reuse_their_buffers() {
+        let (mut simulator, shot) = shot_towards_foot_left_corner();
+        simulator.apply_shot(&shot);
+        let mut copy = simulator.clone();
+
+        let mut state = SimulationState { t: 0., balls: Vec::new() };
+        simulator.step();
+        simulator.snapshot_into(&mut state);
+        copy.step();
+        let buffer = state.balls.as_ptr();
+        for _ in 0..5000 {
+            simulator.step();
+            simulator.snapshot_into(&mut state);
+            let progressed = copy.progress();
+            assert_eq!(state.balls.as_ptr(), buffer);
+            assert_eq!(state.t, progressed.t);
+            assert!(same_balls(&state.balls, &progressed.balls));
+        }
+        assert_eq!(simulator.events, copy.events);
+    }
+
+    #[test]
+    fn test_state_ring() {
+        let (mut simulator, shot) = shot_towards_foot_left_corner();
+        simulator.apply_shot(&shot);
+        let mut ring = StateRing::new(3, simulator.balls.len());
+        let mut seq = SimulationStateSeq { states: vec![] };
+        assert!(ring.is_empty() && ring.latest().is_none());
+
+        let buffers: Vec<_> = (0..3).map(|k| {
+            simulator.step();
+            ring.push_from(&simulator);
+            seq.states.push(SimulationState { t: simulator.t, balls: simulator.balls.clone() });
+            ring.get(k).balls.as_ptr()
+        }).collect();
+        for _ in 0..4 {
+            simulator.step();
+            ring.push_from(&simulator);
+            seq.states.push(SimulationState { t: simulator.t, balls: simulator.balls.clone() });
+        }
+
+        // The three latest of seven states, oldest first, in the slots
+        // allocated at the start.
+        assert_eq!(ring.len(), 3);
+        for k in 0..3 {
+            assert_eq!(ring.get(k).t, seq.states[4 + k].t);
+            assert!(same_balls(&ring.get(k).balls, &seq.states[4 + k].balls));
+            assert!(buffers.contains(&ring.get(k).balls.as_ptr()));
+        }
+        assert_eq!(ring.latest().unwrap().t, simulator.t);
+
+        seq.states.drain(..4);
+        for &dt in [-1e-3, 0., 0.3e-4, 1.5e-4, 1e-3].iter() {
+            let t = seq.states[0].t + dt;
+            assert!(same_balls(&ring.calc_interpolated_at(t).balls, &seq.calc_interpolated_at(t).balls));
+        }
+
+        ring.clear();
+        assert!(ring.is_empty());
+    }
+
+    fn contacts_of(simulator: &Simulator, ball: usize) -> usize {
+        simulator.events.iter().filter(|e| match **e {
+            SimulationEvent::BallBall { i, j, .. } => i == ball || j == ball,
+            _ => false,
+        }).count()
+    }
+
+    #[test]
+    fn test_jump_shot_clears_blocking_ball() {
+        let play = |elevation: f64| {
+            let balls = vec![ball_at_rest(-0.9, 0.), ball_at_rest(-0.26, 0.)];
+            let mut simulator = Simulator::new(balls, WorldConf::pool(), 1e-4);
+            let strike = CueStrike { ball: 0, speed: 5., aim: 0., elevation: elevation };
+            simulator.apply_shot(&strike.to_shot());
+            for _ in 0..5000 {
+                simulator.progress();
+            }
+            simulator
+        };
+
+        let simulator = play(45f64.to_radians());
+        assert_eq!(contacts_of(&simulator, 1), 0);
+        assert!(simulator.balls[0].pos.x > simulator.balls[1].pos.x);
+        assert!(simulator.events.iter().any(|e| match *e {
+            SimulationEvent::BallCloth { i: 0, .. } => true,
+            _ => false,
+        }));
+
+        // With a level cue the same stroke runs into the blocker.
+        assert!(contacts_of(&play(0.), 1) > 0);
+    }
+
+    #[test]
+    fn test_balls_above_the_cushion_noses_fly_over() {
+        // Towards the foot cushion from 5 cm away, at two heights.
+        let hl = consts::POOL_TABLE_LENGTH / 2.;
+        let play = |height: f64| {
+            let mut ball = ball_at_rest(hl - 0.05 - consts::POOL_BALL_RADIUS, 0.);
+            ball.pos.z += height;
+            ball.u = JVector3::new(2., 0., 0.);
+            let mut simulator = Simulator::new(vec![ball], WorldConf::pool(), 1e-4);
+            for _ in 0..500 {
+                simulator.progress();
+            }
+            simulator
+        };
+        let is_cushion = |e: &SimulationEvent| match *e {
+            SimulationEvent::BallCushion { .. } => true,
+            _ => false,
+        };
+
+        let high = play(0.1);
+        assert!(!high.events.iter().any(&is_cushion));
+        assert!(high.balls[0].pos.x > hl);
+
+        let low = play(0.01);
+        assert!(low.events.iter().any(&is_cushion));
+        assert!(low.balls[0].pos.x < hl);
+    }
+
+    #[test]
+    fn test_ball_landing_on_another_stays_on_cloth() {
+        let r = consts::POOL_BALL_RADIUS;
+        for &offset in [0., 0.01].iter() {
+            let mut upper = Ball::at_rest(JVector3::new(offset, 0., 3. * r + 0.0001));
+            upper.u = JVector3::new(0., 0., -1.);
+            let mut simulator = Simulator::new(vec![ball_at_rest(0., 0.), upper], WorldConf::pool(), 1e-4);
+            for _ in 0..100 {
+                simulator.progress();
+            }
+
+            let lower = &simulator.balls[0];
+            assert!((lower.pos.z - r).abs() < 1e-9);
+            assert!(lower.u.z.abs() < 1e-6);
+            assert!(simulator.balls[1].u.z > 0.);
+            assert_eq!(contacts_of(&simulator, 0), 1);
+            if offset > 0. {
+                // Pushed sideways, away from the upper ball.
+                assert!(lower.u.x < 0.);
+            }
+        }
+    }
 
-        for stepi in 0..1000 {
+    #[test]
+    fn test_landing_bounces_retain_spin() {
+        // The spin matches the speed, so there is no slip when the ball
+        // touches down and no friction to change the spin.
+        let mut ball = Ball::at_rest(JVector3::new(0., 0., consts::POOL_BALL_RADIUS + 0.1));
+        ball.u = JVector3::new(0.5, 0., 0.);
+        ball.omega = JVector3::new(0., 0.5 / consts::POOL_BALL_RADIUS, 0.);
+        let mut simulator = Simulator::new(vec![ball], open_cloth_world_conf(), 1e-4);
+
+        let mut peaks = Vec::new();
+        let mut rising = false;
+        let mut previous_z = simulator.balls[0].pos.z;
+        for _ in 0..10000 {
+            simulator.progress();
+            let z = simulator.balls[0].pos.z;
+            if rising && z < previous_z {
+                peaks.push(previous_z);
+            }
+            rising = z > previous_z;
+            previous_z = z;
+        }
+
+        assert!(peaks.len() >= 2);
+        for k in 1..peaks.len() {
+            assert!(peaks[k] < peaks[k - 1]);
+        }
+        let ball = &simulator.balls[0];
+        assert_eq!(ball.omega, JVector3::new(0., 0.5 / consts::POOL_BALL_RADIUS, 0.));
+    }
+
+    #[test]
+    fn test_ball_in_the_air_flies_over_pocket() {
+        let pocket = JVector3::new(0., -consts::POOL_TABLE_WIDTH / 2., 0.);
+        let ball = Ball::at_rest(JVector3::new(pocket.x, pocket.y, consts::POOL_BALL_RADIUS + 0.05));
+        let mut simulator = Simulator::new(vec![ball], WorldConf::pool(), 1e-4);
+        for _ in 0..500 {
+            simulator.progress();
+        }
+        assert!(!simulator.balls[0].pocketed);
+        for _ in 0..1000 {
+            simulator.progress();
+        }
+        match simulator.events[..] {
+            [SimulationEvent::BallPocket { t, i: 0, pocket: 2 }] => assert!((t - 0.1).abs() < 0.01),
+            ref other => panic!("unexpected events {:?}", other),
+        }
+    }
+
+    fn roll_on_open_cloth(ball: Ball, steps: usize) -> Ball {
+        let mut simulator = Simulator::new(vec![ball], open_cloth_world_conf(), 1e-4);
+        for _ in 0..steps {
             simulator.progress();
-            println!("{:?} {:?}", simulator.balls[0].pos, simulator.balls[1].pos);
-            println!("{:?} {:?}", simulator.balls[0].u, simulator.balls[1].u);
-            println!("");
+        }
+        simulator.balls[0].clone()
+    }
+
+    fn spinning_ball(u: JVector3, omega: JVector3) -> Ball {
+        let mut ball = ball_at_rest(0., 0.);
+        ball.u = u;
+        ball.omega = omega;
+        ball
+    }
+
+    #[test]
+    fn test_sliding_ball_ends_in_natural_roll() {
+        let r = consts::POOL_BALL_RADIUS;
+        let ball = roll_on_open_cloth(spinning_ball(JVector3::new(2., 0., 0.), JVector3::zeros()), 5000);
+        // Friction keeps the angular momentum about the contact point, so a
+        // stun shot rolls on at 5/7 of its speed.
+        assert!((ball.u.x - 2. * 5. / 7.).abs() < 1e-9);
+        assert!((ball.omega - JVector3::new(0., ball.u.x / r, 0.)).norm() < 1e-6);
+
+        // A draw shot slows down faster and ends up rolling more slowly.
+        let draw = roll_on_open_cloth(spinning_ball(JVector3::new(2., 0., 0.), JVector3::new(0., -2. / r, 0.)), 10000);
+        assert!((draw.u.x - 6. / 7.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_masse_curves_the_path() {
+        let r = consts::POOL_BALL_RADIUS;
+        let u = JVector3::new(1., 0., 0.);
+        // Axis tilted from vertical towards the direction of travel.
+        let masse = |tilt: f64| roll_on_open_cloth(spinning_ball(u, JVector3::new(tilt, 0., 1.) * 40. / r), 5000);
+
+        let left = masse(0.3);
+        let right = masse(-0.3);
+        assert!(left.pos.y < -0.01);
+        assert!((left.pos.y + right.pos.y).abs() < 1e-12);
+        assert!(left.u.y < 0.);
+
+        // Pure sidespin with a vertical axis does not bend the path.
+        let side = masse(0.);
+        assert_eq!(side.pos.y, 0.);
+        assert!((side.u.x - 5. / 7.).abs() < 1e-9);
+    }
+
+    // Drops a ball from 5 cm onto open cloth and returns it right after its
+    // first bounce.
+    fn bounce(u: JVector3, omega: JVector3) -> Ball {
+        let mut ball = spinning_ball(u, omega);
+        ball.pos.z += 0.05;
+        let mut simulator = Simulator::new(vec![ball], open_cloth_world_conf(), 1e-4);
+        while simulator.events.is_empty() {
+            simulator.progress();
+        }
+        simulator.balls[0].clone()
+    }
+
+    #[test]
+    fn test_topspin_bounce_throws_ball_forward() {
+        let r = consts::POOL_BALL_RADIUS;
+        let ball = bounce(JVector3::new(1., 0., 0.), JVector3::new(0., 2. / r, 0.));
+        // The friction the impact allows is enough to stop the slip, so the
+        // ball leaves the cloth rolling.
+        assert!((ball.u.x - 9. / 7.).abs() < 1e-9);
+        assert!((ball.omega - JVector3::new(0., ball.u.x / r, 0.)).norm() < 1e-6);
+        assert!(ball.u.z > 0.);
+    }
+
+    #[test]
+    fn test_backspin_bounce_holds_ball_back() {
+        let r = consts::POOL_BALL_RADIUS;
+        let ball = bounce(JVector3::new(1., 0., 0.), JVector3::new(0., -1. / r, 0.));
+        // The slip is too large to be stopped by one impact, so the full
+        // friction impulse applies.
+        let speed_into_cloth = ball.u.z / consts::BALL_CLOTH_REST;
+        let du = consts::BALL_CLOTH_FRICTION * (1. + consts::BALL_CLOTH_REST) * speed_into_cloth;
+        assert!((ball.u.x - (1. - du)).abs() < 1e-9);
+        assert!(ball.omega.y > -1. / r);
+        assert_eq!(ball.u.y, 0.);
+    }
+
+    #[test]
+    fn test_sidespin_bounce_changes_nothing() {
+        let omega = JVector3::new(0., 0., 30.);
+        let ball = bounce(JVector3::zeros(), omega);
+        assert_eq!((ball.u.x, ball.u.y), (0., 0.));
+        assert!((ball.omega - omega).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_impulse_helpers() {
+        let world_conf = WorldConf::pool();
+        let m = world_conf.ball_weight;
+        let r = world_conf.ball_radius;
+        let inertia = world_conf.ball_moment_of_inertia();
+        assert!((inertia - 0.4 * m * r * r).abs() < 1e-18);
+
+        // Through the centre only the velocity changes.
+        let mut ball = ball_at_rest(0., 0.);
+        ball.apply_impulse(&JVector3::new(m, 0., 0.), &JVector3::new(-r, 0., 0.), &world_conf);
+        assert_eq!(ball.u, JVector3::new(1., 0., 0.));
+        assert_eq!(ball.omega, JVector3::zeros());
+
+        // A cue tip striking above the centre gives topspin.
+        let mut ball = ball_at_rest(0., 0.);
+        ball.apply_impulse(&JVector3::new(m, 0., 0.), &JVector3::new(0., 0., r / 2.), &world_conf);
+        assert_eq!(ball.u, JVector3::new(1., 0., 0.));
+        assert!((ball.omega - JVector3::new(0., m * r / 2. / inertia, 0.)).norm() < 1e-9);
+
+        let mut ball = ball_at_rest(0., 0.);
+        ball.apply_torque(&JVector3::new(0., 0., inertia), 0.5, &world_conf);
+        assert!((ball.omega - JVector3::new(0., 0., 0.5)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_spin_faster_than_a_turn_per_second() {
+        // Five turns per second; after a tenth of a second the ball has
+        // turned half a turn.
+        let mut ball = ball_at_rest(0., 0.);
+        ball.omega = JVector3::new(0., 0., 10. * consts::PI);
+        for _ in 0..10 {
+            ball.apply_velocities(0.01, false);
+        }
+        let x = ball.rot * JVector3::new(1., 0., 0.);
+        assert!((x - JVector3::new(-1., 0., 0.)).norm() < 1e-9);
+    }
+
+    fn roll_with_conditions(ball: Ball, conditions: TableConditions, steps: usize) -> Ball {
+        let mut world_conf = open_cloth_world_conf();
+        world_conf.conditions = conditions;
+        let mut simulator = Simulator::new(vec![ball], world_conf, 1e-4);
+        for _ in 0..steps {
+            simulator.progress();
+        }
+        simulator.balls[0].clone()
+    }
+
+    #[test]
+    fn test_ball_rolls_down_slope() {
+        let mut conditions = TableConditions::level();
+        conditions.slope = JVector3::new(0.01, 0., 0.);
+        let ball = roll_with_conditions(ball_at_rest(0., 0.), conditions, 10000);
+        // A rolling ball accelerates at 5/7 of g sin(angle).
+        assert!((ball.u.x - 5. / 7. * -consts::GRAVITY * 0.01).abs() < 1e-9);
+        assert_eq!(ball.u.y, 0.);
+        assert!((ball.omega.y - ball.u.x / consts::POOL_BALL_RADIUS).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_nap_bends_slow_balls_only() {
+        let mut conditions = TableConditions::level();
+        conditions.nap = Some(Nap {
+            direction: JUnitVector3::new_normalize(JVector3::new(1., 0., 0.)),
+            strength: 0.01,
+            max_speed: 0.3,
+        });
+        let rolling = |speed: f64| {
+            let mut ball = ball_at_rest(0., 0.);
+            ball.u = JVector3::new(0., speed, 0.);
+            ball.omega = JVector3::new(-speed / consts::POOL_BALL_RADIUS, 0., 0.);
+            roll_with_conditions(ball, conditions.clone(), 10000)
+        };
+
+        assert!(rolling(0.1).pos.x > 0.);
+        assert_eq!(rolling(0.5).pos.x, 0.);
+        assert_eq!(rolling(0.).pos, ball_at_rest(0., 0.).pos);
+    }
+
+    #[test]
+    fn test_roll_off_deflects_balls_crossing_it() {
+        let mut conditions = TableConditions::level();
+        conditions.roll_offs.push(RollOff {
+            centre: JVector3::new(0.5, 0.2, 0.),
+            radius: 0.3,
+            slope: JVector3::new(0., 0.01, 0.),
+        });
+        let rolling = |y: f64| {
+            let mut ball = ball_at_rest(0., y);
+            ball.u = JVector3::new(1., 0., 0.);
+            ball.omega = JVector3::new(0., 1. / consts::POOL_BALL_RADIUS, 0.);
+            roll_with_conditions(ball, conditions.clone(), 10000)
+        };
+
+        let crossing = rolling(0.1);
+        assert!(crossing.pos.y > 0.1);
+        assert!(crossing.u.y > 0.);
+        assert_eq!(rolling(-0.3).pos.y, -0.3);
+    }
+
+    #[test]
+    fn test_rolling_resistance_and_spin_decay() {
+        let r = consts::POOL_BALL_RADIUS;
+        let mut ball = ball_at_rest(0., 0.);
+        ball.u = JVector3::new(1., 0., 0.);
+        ball.omega = JVector3::new(0., 1. / r, 5.);
+        let mut world_conf = WorldConf::pool();
+        world_conf.table = None;
+        let mut simulator = Simulator::new(vec![ball], world_conf, 1e-4);
+
+        for _ in 0..2000 {
+            simulator.progress();
+        }
+        // A rolling ball stays rolling while it slows down.
+        let ball = &simulator.balls[0];
+        let slowed = 1. - consts::BALL_CLOTH_ROLLING_RESISTANCE * -consts::GRAVITY * 0.2;
+        assert!((ball.u.x - slowed).abs() < 1e-9);
+        assert!((ball.omega.y - ball.u.x / r).abs() < 1e-6);
+        assert!((ball.omega.z - (5. - consts::BALL_CLOTH_SPIN_DECAY * 0.2)).abs() < 1e-9);
+
+        // It stops after v^2 / (2 mu g).
+        simulator.run_until_rest(30.);
+        let distance = 1. / (2. * consts::BALL_CLOTH_ROLLING_RESISTANCE * -consts::GRAVITY);
+        assert!((simulator.balls[0].pos.x - distance).abs() < 1e-3);
+        assert!(simulator.is_at_rest());
+    }
+
+    #[test]
+    fn test_rolling_resistance_takes_energy_from_drawn_balls() {
+        // Without friction nothing else acts on the ball, so the draw can
+        // only be slowed by the resistance.
+        let mut world_conf = WorldConf::pool();
+        world_conf.table = None;
+        world_conf.ball_cloth_friction = 0.;
+        world_conf.ball_cloth_spin_decay = 0.;
+        let mut ball = ball_at_rest(0., 0.);
+        ball.u = JVector3::new(0.2, 0., 0.);
+        ball.omega = JVector3::new(0., -50., 0.);
+        let mut simulator = Simulator::new(vec![ball], world_conf, 1e-4);
+        let mut previous = energy(&simulator);
+        for _ in 0..1000 {
+            simulator.progress();
+            let now = energy(&simulator);
+            assert!(now <= previous + 1e-12);
+            previous = now;
+        }
+        assert!(simulator.balls[0].omega.y > -50.);
+    }
+
+    // Validation against analytic results. Each test sets up a situation for
+    // which the outcome is known in closed form from the physics of ideal
+    // balls, and checks the simulation against it.
+
+    // A cue ball travelling along +x hits an object ball at the origin, with
+    // the line of centres at cut_angle to its direction of travel. The balls
+    // are 0.1 mm apart, so the cue ball arrives with the spin it was given,
+    // and the ball-to-ball collision is elastic. Returns the simulator right
+    // after the collision.
+    fn cut_shot(cut_angle: f64, speed: f64, omega: JVector3) -> Simulator {
+        let r = consts::POOL_BALL_RADIUS;
+        let mut cue_ball = ball_at_rest(-2. * r * cut_angle.cos() - 0.0001, 2. * r * cut_angle.sin());
+        cue_ball.u = JVector3::new(speed, 0., 0.);
+        cue_ball.omega = omega;
+        let mut world_conf = open_cloth_world_conf();
+        world_conf.ball_ball_rest = 1.;
+        let mut simulator = Simulator::new(vec![cue_ball, ball_at_rest(0., 0.)], world_conf, 1e-4);
+        while contacts_of(&simulator, 0) == 0 {
+            simulator.progress();
+        }
+        simulator
+    }
+
+    fn angle_between(a: &JVector3, b: &JVector3) -> f64 {
+        (a.dot(b) / (a.norm() * b.norm())).max(-1.).min(1.).acos()
+    }
+
+    #[test]
+    fn test_stun_shot_follows_the_90_degree_rule() {
+        // A cue ball without spin leaves an elastic collision at a right
+        // angle to the object ball, whatever the cut, and goes on along the
+        // tangent line.
+        for &degrees in [10., 30., 45., 60.].iter() {
+            let mut simulator = cut_shot(f64::to_radians(degrees), 1., JVector3::zeros());
+            let cue_u = simulator.balls[0].u;
+            let object_u = simulator.balls[1].u;
+            assert!((angle_between(&cue_u, &object_u).to_degrees() - 90.).abs() < 0.1);
+
+            for _ in 0..5000 {
+                simulator.progress();
+            }
+            let travel = simulator.balls[0].u;
+            assert!(angle_between(&travel, &cue_u).to_degrees() < 0.5);
+        }
+    }
+
+    #[test]
+    fn test_rolling_cut_follows_the_30_degree_rule() {
+        // A rolling cue ball is bent forward by its roll after the collision.
+        // For an elastic collision at cut angle phi it ends up deflected from
+        // its original direction by theta, where
+        //     tan(theta) = sin(phi) cos(phi) / (sin(phi)^2 + 2/5),
+        // which lies within a few degrees of 30 for all cuts between a
+        // quarter-ball and a three-quarter-ball hit.
+        let r = consts::POOL_BALL_RADIUS;
+        for &fraction in [0.25, 0.5, 0.75].iter() {
+            let cut_angle = f64::asin(1. - fraction);
+            let mut simulator = cut_shot(cut_angle, 1., JVector3::new(0., 1. / r, 0.));
+            // The cut as it happened, from the direction of the object ball,
+            // which leaves along the line of centres.
+            let object_u = simulator.balls[1].u;
+            let phi = f64::atan2(-object_u.y, object_u.x);
+            assert!((phi - cut_angle).abs() < 0.01);
+
+            for _ in 0..10000 {
+                simulator.progress();
+            }
+            let travel = simulator.balls[0].u;
+            let deflection = f64::atan2(travel.y, travel.x);
+            let expected = f64::atan(phi.sin() * phi.cos() / (phi.sin().powi(2) + 0.4));
+            assert!((deflection - expected).abs().to_degrees() < 0.5);
+            assert!(deflection.to_degrees() > 27. && deflection.to_degrees() < 34.);
+        }
+    }
+
+    #[test]
+    fn test_bounce_heights_decay_with_ball_cloth_rest() {
+        // Every bounce leaves the cloth at ball_cloth_rest times the speed it
+        // hit it with, so each peak is ball_cloth_rest^2 times as high as the
+        // one before.
+        let r = consts::POOL_BALL_RADIUS;
+        let ball = Ball::at_rest(JVector3::new(0., 0., r + 0.2));
+        let mut simulator = Simulator::new(vec![ball], open_cloth_world_conf(), 1e-5);
+
+        let mut peaks = vec![0.2];
+        let mut rising = false;
+        let mut previous_z = simulator.balls[0].pos.z;
+        while peaks.len() < 4 {
+            simulator.progress();
+            let z = simulator.balls[0].pos.z;
+            if rising && z < previous_z {
+                peaks.push(previous_z - r);
+            }
+            rising = z > previous_z;
+            previous_z = z;
+        }
+
+        let expected = consts::BALL_CLOTH_REST.powi(2);
+        for k in 1..peaks.len() {
+            assert!((peaks[k] / peaks[k - 1] - expected).abs() < 0.01 * expected);
+        }
+    }
+
+    #[test]
+    fn test_stun_shot_distance_relative_to_natural_roll() {
+        // A ball struck without spin slides until friction has brought it to
+        // natural roll. The slip v - omega R falls at 7/2 mu g, so the
+        // sliding lasts T = 2 v / (7 mu g). The ball slows at (mu + mu_r) g
+        // meanwhile, mu_r g being the rolling resistance, and then rolls for
+        // a further v_T^2 / (2 mu_r g). A ball that starts in natural roll
+        // only rolls, and covers v^2 / (2 mu_r g).
+        let r = consts::POOL_BALL_RADIUS;
+        let mu = consts::BALL_CLOTH_FRICTION;
+        let mu_r = consts::BALL_CLOTH_ROLLING_RESISTANCE;
+        let g = -consts::GRAVITY;
+        let v = 1.;
+
+        let mut world_conf = WorldConf::pool();
+        world_conf.table = None;
+        let distance = |omega: JVector3, sliding_distance: &mut f64| {
+            let mut ball = ball_at_rest(0., 0.);
+            ball.u = JVector3::new(v, 0., 0.);
+            ball.omega = omega;
+            let mut simulator = Simulator::new(vec![ball], world_conf.clone(), 1e-4);
+            while (simulator.balls[0].u.x - simulator.balls[0].omega.y * r).abs() > 1e-9 {
+                simulator.progress();
+            }
+            *sliding_distance = simulator.balls[0].pos.x;
+            simulator.run_until_rest(30.);
+            assert!(simulator.is_at_rest());
+            simulator.balls[0].pos.x
+        };
+
+        let t_sliding = 2. * v / (7. * mu * g);
+        let v_rolling = v - (mu + mu_r) * g * t_sliding;
+        let expected_sliding = v * t_sliding - (mu + mu_r) * g * t_sliding.powi(2) / 2.;
+        let expected_stun = expected_sliding + v_rolling.powi(2) / (2. * mu_r * g);
+        let expected_roll = v.powi(2) / (2. * mu_r * g);
+
+        let mut sliding = 0.;
+        let stun = distance(JVector3::zeros(), &mut sliding);
+        let roll = distance(JVector3::new(0., v / r, 0.), &mut 0.);
+        assert!((sliding - expected_sliding).abs() < 1e-3);
+        assert!((stun - expected_stun).abs() < 2e-3);
+        assert!((roll - expected_roll).abs() < 2e-3);
+        // For a cloth with little rolling resistance the ratio approaches
+        // (5/7)^2, the ratio of the kinetic energies of the two rolling balls.
+        assert!((stun / roll - expected_stun / expected_roll).abs() < 1e-3);
+        assert!((stun / roll - 25. / 49.).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_cushion_rebound_angles() {
+        // A cushion without friction reverses the speed into it, reduced by
+        // ball_cushion_rest, and keeps the speed along it. Measured from the
+        // normal of the cushion, a ball coming in at alpha leaves at beta
+        // with tan(beta) = tan(alpha) / ball_cushion_rest.
+        let mut world_conf = WorldConf::pool();
+        world_conf.ball_cloth_friction = 0.;
+        world_conf.ball_cloth_rolling_resistance = 0.;
+        let e = consts::BALL_CUSHION_REST;
+        for &degrees in [0., 15., 30., 45., 60.].iter() {
+            let alpha = f64::to_radians(degrees);
+            let mut ball = ball_at_rest(consts::POOL_TABLE_LENGTH / 2. - 0.1, 0.);
+            ball.u = JVector3::new(alpha.cos(), alpha.sin(), 0.);
+            let mut simulator = Simulator::new(vec![ball], world_conf.clone(), 1e-4);
+            while simulator.events.is_empty() {
+                simulator.progress();
+            }
+
+            match simulator.events[..] {
+                [SimulationEvent::BallCushion { i: 0, .. }] => {}
+                ref other => panic!("unexpected events {:?}", other),
+            }
+            let u = simulator.balls[0].u;
+            let beta = f64::atan2(u.y, -u.x);
+            assert!((beta.tan() - alpha.tan() / e).abs() < 1e-9);
+            assert!((u.norm() - (e.powi(2) * alpha.cos().powi(2) + alpha.sin().powi(2)).sqrt()).abs() < 1e-9);
+        }
+    }
+
+    // Invariants checked on generated scenarios. Every test plays a number of
+    // random scenarios from a fixed seed, so a failure can be reproduced.
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([11; 16])
+    }
+
+    // n balls placed at random on the pool table without overlapping, up to
+    // max_height above the cloth. Each moves at up to 3 m/s, with a spin
+    // between none and natural roll and some sidespin.
+    fn random_balls<R: Rng>(rng: &mut R, n: usize, max_height: f64) -> Vec<Ball> {
+        let r = consts::POOL_BALL_RADIUS;
+        let hl = consts::POOL_TABLE_LENGTH / 2. - r - 0.01;
+        let hw = consts::POOL_TABLE_WIDTH / 2. - r - 0.01;
+        let mut balls: Vec<Ball> = Vec::new();
+        while balls.len() < n {
+            let height = rng.gen_range(0., max_height + 1e-12);
+            let pos = JVector3::new(rng.gen_range(-hl, hl), rng.gen_range(-hw, hw), r + height);
+            if balls.iter().any(|ball| (ball.pos - pos).norm() < 2. * r) {
+                continue;
+            }
+            let mut ball = Ball::at_rest(pos);
+            let angle = rng.gen_range(0., 2. * consts::PI);
+            ball.u = JVector3::new(angle.cos(), angle.sin(), 0.) * rng.gen_range(0., 3.);
+            // From draw to twice natural roll.
+            ball.omega = JVector3::new(-ball.u.y, ball.u.x, 0.) / r * rng.gen_range(-1.5, 2.);
+            ball.omega.z = rng.gen_range(-30., 30.);
+            balls.push(ball);
+        }
+        balls
+    }
+
+    fn energy(simulator: &Simulator) -> f64 {
+        let world_conf = &simulator.world_conf;
+        let m = world_conf.ball_weight;
+        let i = world_conf.ball_moment_of_inertia();
+        simulator.balls.iter().filter(|ball| !ball.pocketed).map(|ball| {
+            m * ball.u.norm_squared() / 2.
+                + i * ball.omega.norm_squared() / 2.
+                + m * -world_conf.gravity * (ball.pos.z - world_conf.ball_radius)
+        }).sum()
+    }
+
+    #[test]
+    fn test_elastic_collisions_conserve_momentum() {
+        // On an open cloth without friction the balls only push on each
+        // other, so with restitution 1 the total momentum along the cloth
+        // never changes.
+        let mut world_conf = open_cloth_world_conf();
+        world_conf.ball_ball_rest = 1.;
+        world_conf.ball_cloth_friction = 0.;
+        let mut rng = rng();
+        for _ in 0..5 {
+            let mut simulator = Simulator::new(random_balls(&mut rng, 10, 0.), world_conf.clone(), 1e-4);
+            // Aim everything at the middle, to get there in a third of a
+            // second, so that there are collisions.
+            for ball in simulator.balls.iter_mut() {
+                ball.u = -JVector3::new(ball.pos.x, ball.pos.y, 0.) * 3.;
+            }
+            let momentum = |simulator: &Simulator| {
+                simulator.balls.iter().fold(JVector3::zeros(), |sum, ball| sum + ball.u)
+            };
+            let before = momentum(&simulator);
+            for _ in 0..5000 {
+                simulator.progress();
+                let now = momentum(&simulator);
+                assert!((now.x - before.x).abs() < 1e-9 && (now.y - before.y).abs() < 1e-9);
+            }
+            assert!(simulator.events.iter().any(|e| match *e {
+                SimulationEvent::BallBall { .. } => true,
+                _ => false,
+            }));
+        }
+    }
+
+    #[test]
+    fn test_energy_never_increases_and_overlaps_do_not_last() {
+        // Kinetic and potential energy together, on a pool table with
+        // cushions, pockets and every loss switched on. Balls that touch may
+        // overlap a little within a step but are pushed apart in the steps
+        // that follow.
+        let r = consts::POOL_BALL_RADIUS;
+        let mut rng = rng();
+        for _ in 0..5 {
+            let balls = random_balls(&mut rng, 10, 0.);
+            let n = balls.len();
+            let mut simulator = Simulator::new(balls, WorldConf::pool(), 1e-4);
+            let mut overlapping_for = vec![vec![0; n]; n];
+            let mut previous = energy(&simulator);
+            for _ in 0..5000 {
+                simulator.progress();
+                let now = energy(&simulator);
+                assert!(now <= previous + 1e-12);
+                previous = now;
+
+                for i in 0..n {
+                    for j in i+1..n {
+                        let (a, b) = (&simulator.balls[i], &simulator.balls[j]);
+                        if !a.pocketed && !b.pocketed && (a.pos - b.pos).norm() < 2. * r {
+                            overlapping_for[i][j] += 1;
+                            assert!(overlapping_for[i][j] < 10);
+                        }
+                        else {
+                            overlapping_for[i][j] = 0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_balls_stay_above_cloth_with_normalized_rotations() {
+        // Balls dropped from up to 20 cm, landing on the cloth and on each
+        // other. A falling ball can sink into the cloth by at most the
+        // distance it covers in one step before it bounces.
+        let r = consts::POOL_BALL_RADIUS;
+        let ts = 1e-4;
+        let max_sink = (2. * -consts::GRAVITY * 0.2).sqrt() * ts * 1.01;
+        let mut rng = rng();
+        for _ in 0..5 {
+            let mut simulator = Simulator::new(random_balls(&mut rng, 10, 0.2), WorldConf::pool(), ts);
+            for _ in 0..5000 {
+                simulator.progress();
+                for ball in simulator.balls.iter().filter(|ball| !ball.pocketed) {
+                    assert!(ball.pos.z >= r - max_sink);
+                    assert!((ball.rot.quaternion().norm() - 1.).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolation_is_continuous_and_bracketed() {
+        let mut rng = rng();
+        let mut simulator = Simulator::new(random_balls(&mut rng, 10, 0.1), WorldConf::pool(), 1e-3);
+        let mut seq = SimulationStateSeq { states: vec![] };
+        for _ in 0..1000 {
+            seq.states.push(simulator.progress());
+        }
+        let states = &seq.states;
+        let (t_first, t_last) = (states[0].t, states[states.len() - 1].t);
+
+        for _ in 0..1000 {
+            let t = rng.gen_range(t_first, t_last);
+            let k = states.iter().position(|state| state.t > t).unwrap() - 1;
+            let (a, b) = (&states[k], &states[k + 1]);
+            let interpolated = seq.calc_interpolated_at(t);
+            let nearby = seq.calc_interpolated_at(t + 1e-9);
+            assert_eq!(interpolated.t, t);
+
+            for i in 0..a.balls.len() {
+                let pos = interpolated.balls[i].pos;
+                for c in 0..3 {
+                    let (p, q) = (a.balls[i].pos[c], b.balls[i].pos[c]);
+                    assert!(pos[c] >= p.min(q) - 1e-12 && pos[c] <= p.max(q) + 1e-12);
+                }
+                let rot = interpolated.balls[i].rot;
+                let turn = a.balls[i].rot.angle_to(&b.balls[i].rot);
+                assert!(a.balls[i].rot.angle_to(&rot) <= turn + 1e-9);
+                assert!(rot.angle_to(&b.balls[i].rot) <= turn + 1e-9);
+
+                // Positions move at most at the speed between the states.
+                let speed = (b.balls[i].pos - a.balls[i].pos).norm() / (b.t - a.t);
+                assert!((nearby.balls[i].pos - pos).norm() <= speed * 1e-9 + 1e-12);
+            }
+        }
+
+        // At the stored times, approached from either side, the interpolation
+        // gives the stored states.
+        for k in 1..states.len() - 1 {
+            let t = states[k].t;
+            for &dt in [-1e-12, 0., 1e-12].iter() {
+                let interpolated = seq.calc_interpolated_at(t + dt);
+                for (ball, stored) in interpolated.balls.iter().zip(states[k].balls.iter()) {
+                    assert!((ball.pos - stored.pos).norm() < 1e-9);
+                }
+            }
         }
     }
 }
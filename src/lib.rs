@@ -0,0 +1,14 @@
+extern crate rand;
+
+pub mod appearance;
+pub mod batch;
+pub mod calibration;
+pub mod cloth;
+#[cfg(test)]
+mod fixtures;
+pub mod geometry;
+pub mod golden;
+pub mod jlib;
+pub mod rules;
+pub mod scene_file;
+pub mod tracking;
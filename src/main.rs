@@ -1,26 +1,25 @@
 extern crate ggez;
+extern crate justshoot;
 use ggez::*;
 
-mod jlib;
-mod geometry;
-
-use jlib::{
+use justshoot::jlib::{
     Ball,
     Simulator,
     SimulationState,
-    SimulationStateSeq,
+    StateRing,
     WorldConf,
-    consts,
 };
 
-use geometry::{
+use justshoot::geometry::{
     JVector3,
-    JUnitVector3,
     JGVector3,
-    JQuaternion,
     JUnitQuaternion,
+    rotate_points,
 };
 
+use justshoot::appearance::{self, BallAppearance, BallSet, Colour};
+use justshoot::tracking::{self, Track};
+
 struct GraphicsConf {
     width: u32,
     height: u32,
@@ -35,9 +34,21 @@ struct GameState {
     graphics_conf: GraphicsConf,
     simulator: Simulator,
     shot_done: bool,
-    simulation_state_seq: SimulationStateSeq,
+    // The two latest states, between which the frame is interpolated.
+    simulation_states: StateRing,
     t_shot_start: Option<f64>,
     simulation_state: Option<SimulationState>,
+    // Measured positions drawn over the simulation, from the CSV file given
+    // as the first argument.
+    tracks: Vec<Track>,
+    t_in_shot: f64,
+    // The balls are numbered by their index in the simulator.
+    ball_set: BallSet,
+    // The spots of every ball at rest, relative to its centre, and the spots
+    // of the ball being drawn turned with it, kept to not allocate every
+    // frame.
+    spots: Vec<Vec<JVector3>>,
+    rotated_spots: Vec<JVector3>,
 }
 
 impl GameState {
@@ -51,27 +62,17 @@ impl GameState {
             should_print_ball_positions: false,
         };
 
-        let world_conf = WorldConf {
-            gravity: consts::GRAVITY,
-            ball_radius: consts::POOL_BALL_RADIUS,
-            ball_weight: consts::POOL_BALL_WEIGHT,
-            ball_ball_rest: consts::BALL_BALL_REST,
-            ball_cloth_rest: consts::BALL_CLOTH_REST,
-            ball_spot_poss: vec![
-                JUnitVector3::new_normalize(JVector3::new(0., 0., 1.)),
-                JUnitVector3::new_normalize(JVector3::new(0., 0., -1.)),
-                JUnitVector3::new_normalize(JVector3::new(1., 0.5, 0.2)),
-            ],
-            ball_spot_radius_factor: consts::BALL_SPOT_RADIUS_FACTOR,
-        };
+        // An open cloth, without cushions or pockets.
+        let mut world_conf = WorldConf::pool();
+        world_conf.table = None;
 
         let balls = vec![
             Ball {
                 pos: JVector3::new(0.0, 0.0, 0.37),
-                urot_axis: JUnitVector3::new_normalize(JVector3::new(-400., 0., 15.)),
-                urot_angle: -100.9 * 3.14,
+                omega: JVector3::new(-400., 0., 15.).normalize() * -100.9 * 3.14,
                 u: JVector3::new(0., 0., 0.),
                 rot: JUnitQuaternion::identity(),
+                pocketed: false,
             },
             // Ball {
             //     pos: JVector3::new(-0.1, 0.0875, 20.),
@@ -88,7 +89,19 @@ impl GameState {
             //     rot: JUnitQuaternion::identity(),
             // },
         ];
-        
+        let n_balls = balls.len();
+        let ball_set = BallSet::pool(BallAppearance::with_spots(
+            appearance::WHITE,
+            &[JVector3::new(0., 0., 1.), JVector3::new(0., 0., -1.), JVector3::new(1., 0.5, 0.2)],
+            appearance::RED,
+            appearance::SPOT_RADIUS_FACTOR,
+        ));
+        let spots: Vec<Vec<JVector3>> = (0..n_balls).map(|i| {
+            ball_set.appearance_of(i as u32).spots.iter()
+                .map(|spot| spot.direction.unwrap() * world_conf.ball_radius)
+                .collect()
+        }).collect();
+
         GameState {
             graphics_conf: graphics_conf,
             simulator: Simulator::new(
@@ -98,12 +111,34 @@ impl GameState {
             ),
             shot_done: false,
             t_shot_start: None,
-            simulation_state_seq: SimulationStateSeq { states: Vec::with_capacity(2) },
+            simulation_states: StateRing::new(2, n_balls),
             simulation_state: None,
+            tracks: load_tracks(),
+            t_in_shot: 0.,
+            ball_set: ball_set,
+            rotated_spots: Vec::new(),
+            spots: spots,
         }
     }
 }
 
+fn load_tracks() -> Vec<Track> {
+    match std::env::args().nth(1) {
+        Some(path) => match tracking::load_csv(&path) {
+            Ok(observations) => tracking::tracks(&observations),
+            Err(e) => {
+                println!("{}: {}", path, e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    }
+}
+
+fn to_color(colour: Colour) -> graphics::Color {
+    graphics::Color::from_rgb(colour.r, colour.g, colour.b)
+}
+
 impl event::EventHandler for GameState {
   fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
     const DESIRED_FPS: u32 = 60;
@@ -120,14 +155,11 @@ impl event::EventHandler for GameState {
         if let Some(t_shot_start) = self.t_shot_start {
 
             let t_elapsed_in_shot = t - t_shot_start;
+            self.t_in_shot = t_elapsed_in_shot;
 
             while self.simulator.t < t_elapsed_in_shot {
-                let simulation_state = self.simulator.progress();
-
-                if self.simulation_state_seq.states.len() == 2 {
-                    self.simulation_state_seq.states.remove(0);
-                }
-                self.simulation_state_seq.states.push(simulation_state);
+                self.simulator.step();
+                self.simulation_states.push_from(&self.simulator);
             }
 
              
@@ -139,9 +171,9 @@ impl event::EventHandler for GameState {
             }
 
 
-            if !self.simulation_state_seq.states.is_empty() {
+            if !self.simulation_states.is_empty() {
                 self.simulation_state = Some(
-                    self.simulation_state_seq.calc_interpolated_at(t_elapsed_in_shot));
+                    self.simulation_states.calc_interpolated_at(t_elapsed_in_shot));
             }
 
             // println!("fps: {:?}", timer::get_fps(ctx));
@@ -157,12 +189,13 @@ impl event::EventHandler for GameState {
     graphics::clear(ctx);
 
     if let Some(ref simulation_state) = self.simulation_state {
-        for ball in self.simulator.balls.iter() {
-            if ball.pos.z < self.graphics_conf.eye_height {
+        for (i, ball) in self.simulator.balls.iter().enumerate() {
+            if !ball.pocketed && ball.pos.z < self.graphics_conf.eye_height {
+                let appearance = self.ball_set.appearance_of(i as u32);
                 let distance = (ball.pos.z - self.graphics_conf.eye_height).abs() as f32;
                 let scale = self.graphics_conf.pixels_per_meter / distance; 
 
-                graphics::set_color(ctx, graphics::Color::from_rgb(255, 255, 255));
+                graphics::set_color(ctx, to_color(appearance.colour));
                 let ball_graphic = graphics::circle(
                     ctx,
                     graphics::DrawMode::Fill,
@@ -194,20 +227,15 @@ impl event::EventHandler for GameState {
                 // );
 
                 // Add some spots on the balls in order to see the rotation.
-                // The spot starts on the top of the ball.
-                for spot_initial_unit in self.simulator.world_conf.ball_spot_poss.iter() {
-                    let spot_initial = spot_initial_unit.unwrap() * self.simulator.world_conf.ball_radius;
-                    let spot_rotated =
-                            ball.rot.quaternion()
-                        *   JQuaternion::new(0., spot_initial.x, spot_initial.y, spot_initial.z)
-                        *   ball.rot.inverse().quaternion();
-                    let spot_as_vector4 = spot_rotated.as_vector();
-                    let spot_as_vector = JVector3::new(spot_as_vector4.x, spot_as_vector4.y, spot_as_vector4.z);
-                    if spot_as_vector.z > 0. {
-
-                        let spot_translated = spot_as_vector + ball.pos;
-
-                        graphics::set_color(ctx, graphics::Color::from_rgb(255, 20, 20));
+                // The rotation matrix of the ball is built once and turns all
+                // of its spots.
+                rotate_points(&self.spots[i], &ball.rot, &mut self.rotated_spots);
+                for (spot, spot_rotated) in appearance.spots.iter().zip(self.rotated_spots.iter()) {
+                    if spot_rotated.z > 0. {
+
+                        let spot_translated = spot_rotated + ball.pos;
+
+                        graphics::set_color(ctx, to_color(spot.colour));
                         let spot_graphic = graphics::circle(
                             ctx,
                             graphics::DrawMode::Fill,
@@ -216,7 +244,7 @@ impl event::EventHandler for GameState {
                                 self.graphics_conf.origin.y + (spot_translated.y as f32) * scale,
                             ),
                             (self.simulator.world_conf.ball_radius as f32)
-                                * (self.simulator.world_conf.ball_spot_radius_factor as f32) * scale,
+                                * (spot.radius_factor as f32) * scale,
                             0.001,
                         );
 
@@ -225,6 +253,26 @@ impl event::EventHandler for GameState {
 
             }
         }
+
+        // The tracked balls, as outlines, while their measurement lasts.
+        let ball_radius = self.simulator.world_conf.ball_radius;
+        let scale = self.graphics_conf.pixels_per_meter
+            / (self.graphics_conf.eye_height - ball_radius) as f32;
+        graphics::set_color(ctx, graphics::Color::from_rgb(0, 255, 0));
+        for track in self.tracks.iter() {
+            if let Some((x, y)) = track.position_at(self.t_in_shot) {
+                graphics::circle(
+                    ctx,
+                    graphics::DrawMode::Line(1.),
+                    graphics::Point2::new(
+                        self.graphics_conf.origin.x + (x as f32) * scale,
+                        self.graphics_conf.origin.y + (y as f32) * scale,
+                    ),
+                    (ball_radius as f32) * scale,
+                    0.001,
+                )?;
+            }
+        }
     }
 
     graphics::present(ctx);
@@ -0,0 +1,408 @@
+// WPA eight-ball. Players are 0 and 1.
+
+use rules::{ShotOutcome, other_player};
+
+pub const EIGHT_BALL: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    Solids,
+    Stripes,
+}
+
+impl Group {
+    pub fn of(ball: u32) -> Option<Group> {
+        match ball {
+            1 ..= 7 => Some(Group::Solids),
+            9 ..= 15 => Some(Group::Stripes),
+            _ => None,
+        }
+    }
+
+    pub fn other(self) -> Group {
+        match self {
+            Group::Solids => Group::Stripes,
+            Group::Stripes => Group::Solids,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Break,
+    // Groups are not yet assigned.
+    Open,
+    // groups[player] is the group of that player.
+    Assigned([Group; 2]),
+    Over { winner: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Foul {
+    Scratch,
+    NoContact,
+    WrongBallFirst(u32),
+    NoRailAfterContact,
+    // Fewer than four object balls reached a cushion and none was pocketed.
+    IllegalBreak,
+}
+
+// Where the incoming player may place the cue ball.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BallInHand {
+    Anywhere,
+    BehindHeadString,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Call {
+    pub ball: u32,
+    pub pocket: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub foul: Option<Foul>,
+    pub turn_passes: bool,
+    // The eight went down on the break and has to be put back on its spot.
+    pub respot_eight: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub phase: Phase,
+    pub shooter: usize,
+    // Object balls still on the table, the eight included.
+    pub on_table: Vec<u32>,
+    pub ball_in_hand: Option<BallInHand>,
+}
+
+impl Game {
+    pub fn new(breaker: usize) -> Self {
+        Game {
+            phase: Phase::Break,
+            shooter: breaker,
+            on_table: (1..16).collect(),
+            ball_in_hand: None,
+        }
+    }
+
+    pub fn group_of(&self, player: usize) -> Option<Group> {
+        match self.phase {
+            Phase::Assigned(groups) => Some(groups[player]),
+            _ => None,
+        }
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        match self.phase {
+            Phase::Over { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    // Whether the player has cleared their group and must now play the eight.
+    pub fn is_on_eight(&self, player: usize) -> bool {
+        match self.group_of(player) {
+            Some(group) => !self.on_table.iter().any(|&ball| Group::of(ball) == Some(group)),
+            None => false,
+        }
+    }
+
+    // The eight has to be called; other balls may be called to make sure
+    // that only the intended ball counts. A call of None means that any
+    // legally pocketed ball counts.
+    pub fn play(&mut self, call: Option<Call>, outcome: &ShotOutcome) -> Verdict {
+        if let Phase::Over { .. } = self.phase {
+            panic!("play called on a finished eight-ball game");
+        }
+
+        self.ball_in_hand = None;
+        let pocketed = outcome.pocketed();
+        let was_on_eight = self.is_on_eight(self.shooter);
+        self.on_table.retain(|ball| !pocketed.contains(ball));
+
+        let verdict = if self.phase == Phase::Break {
+            self.play_break(outcome)
+        }
+        else {
+            self.play_shot(call, outcome, was_on_eight)
+        };
+
+        if verdict.turn_passes {
+            self.shooter = other_player(self.shooter);
+        }
+        verdict
+    }
+
+    fn play_break(&mut self, outcome: &ShotOutcome) -> Verdict {
+        let pocketed = outcome.pocketed();
+
+        let respot_eight = pocketed.contains(&EIGHT_BALL);
+        if respot_eight {
+            self.on_table.push(EIGHT_BALL);
+        }
+
+        let foul = if outcome.is_scratch() {
+            self.ball_in_hand = Some(BallInHand::BehindHeadString);
+            Some(Foul::Scratch)
+        }
+        else if pocketed.is_empty() && outcome.object_balls_to_cushion() < 4 {
+            Some(Foul::IllegalBreak)
+        }
+        else {
+            None
+        };
+
+        // The table is always open after the break, whatever went down.
+        self.phase = Phase::Open;
+
+        let scored = pocketed.iter().any(|&ball| ball != EIGHT_BALL);
+        Verdict {
+            foul: foul,
+            turn_passes: foul.is_some() || !scored,
+            respot_eight: respot_eight,
+        }
+    }
+
+    fn play_shot(&mut self, call: Option<Call>, outcome: &ShotOutcome, was_on_eight: bool) -> Verdict {
+        let pocketed = outcome.pocketed();
+        let first_contact = outcome.first_contact();
+
+        let foul = if outcome.is_scratch() {
+            Some(Foul::Scratch)
+        }
+        else {
+            match first_contact {
+                None => Some(Foul::NoContact),
+                Some(ball) if !self.is_legal_first_contact(ball, was_on_eight) =>
+                    Some(Foul::WrongBallFirst(ball)),
+                Some(_) if pocketed.is_empty() && !outcome.rail_after_contact() =>
+                    Some(Foul::NoRailAfterContact),
+                Some(_) => None,
+            }
+        };
+
+        if pocketed.contains(&EIGHT_BALL) {
+            let called_correctly = match call {
+                Some(call) => call.ball == EIGHT_BALL && outcome.pocket_of(EIGHT_BALL) == Some(call.pocket),
+                None => false,
+            };
+            let winner = if was_on_eight && foul.is_none() && called_correctly {
+                self.shooter
+            }
+            else {
+                other_player(self.shooter)
+            };
+            self.phase = Phase::Over { winner: winner };
+            return Verdict { foul: foul, turn_passes: false, respot_eight: false };
+        }
+
+        if foul.is_some() {
+            self.ball_in_hand = Some(BallInHand::Anywhere);
+            return Verdict { foul: foul, turn_passes: true, respot_eight: false };
+        }
+
+        // On an open table a shot that contacts the eight first is legal,
+        // but nothing it pockets counts for the shooter.
+        if self.phase == Phase::Open && first_contact == Some(EIGHT_BALL) {
+            return Verdict { foul: None, turn_passes: true, respot_eight: false };
+        }
+
+        let counted: Vec<u32> = match call {
+            Some(call) => {
+                if outcome.pocket_of(call.ball) == Some(call.pocket) { vec![call.ball] } else { vec![] }
+            }
+            None => pocketed.clone(),
+        };
+
+        if self.phase == Phase::Open {
+            if let Some(group) = counted.first().and_then(|&ball| Group::of(ball)) {
+                let mut groups = [group.other(); 2];
+                groups[self.shooter] = group;
+                self.phase = Phase::Assigned(groups);
+            }
+        }
+
+        let own_group = self.group_of(self.shooter);
+        let scored = counted.iter().any(|&ball| own_group.is_some() && Group::of(ball) == own_group);
+        Verdict { foul: None, turn_passes: !scored, respot_eight: false }
+    }
+
+    fn is_legal_first_contact(&self, ball: u32, was_on_eight: bool) -> bool {
+        match self.phase {
+            Phase::Assigned(groups) => {
+                if was_on_eight {
+                    ball == EIGHT_BALL
+                }
+                else {
+                    Group::of(ball) == Some(groups[self.shooter])
+                }
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rules::{ShotEvent, ShotOutcome};
+    use rules::eight_ball::{BallInHand, Call, Foul, Game, Group, Phase};
+
+    fn outcome(events: Vec<ShotEvent>) -> ShotOutcome {
+        ShotOutcome::new(events)
+    }
+
+    fn game_after_break() -> Game {
+        let mut game = Game::new(0);
+        game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(3, 0),
+        ]));
+        game
+    }
+
+    #[test]
+    fn test_break_keeps_table_open() {
+        let game = game_after_break();
+        assert_eq!(game.phase, Phase::Open);
+        assert_eq!(game.shooter, 0);
+        assert!(!game.on_table.contains(&3));
+    }
+
+    #[test]
+    fn test_illegal_break() {
+        let mut game = Game::new(0);
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Cushion(1),
+            ShotEvent::Cushion(2),
+        ]));
+        assert_eq!(verdict.foul, Some(Foul::IllegalBreak));
+        assert_eq!(game.shooter, 1);
+    }
+
+    #[test]
+    fn test_eight_on_break_is_respotted() {
+        let mut game = Game::new(0);
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(8, 2),
+        ]));
+        assert!(verdict.respot_eight);
+        assert!(game.on_table.contains(&8));
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.shooter, 1);
+    }
+
+    #[test]
+    fn test_scratch_on_break() {
+        let mut game = Game::new(0);
+        game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(4, 0),
+            ShotEvent::Pocket(0, 5),
+        ]));
+        assert_eq!(game.ball_in_hand, Some(BallInHand::BehindHeadString));
+        assert_eq!(game.shooter, 1);
+    }
+
+    #[test]
+    fn test_group_assignment() {
+        let mut game = game_after_break();
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 12),
+            ShotEvent::Pocket(12, 1),
+        ]));
+        assert_eq!(verdict.foul, None);
+        assert!(!verdict.turn_passes);
+        assert_eq!(game.group_of(0), Some(Group::Stripes));
+        assert_eq!(game.group_of(1), Some(Group::Solids));
+    }
+
+    #[test]
+    fn test_wrong_ball_first() {
+        let mut game = game_after_break();
+        game.play(None, &outcome(vec![ShotEvent::BallBall(0, 12), ShotEvent::Pocket(12, 1)]));
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 2),
+            ShotEvent::BallBall(2, 13),
+            ShotEvent::Pocket(13, 4),
+        ]));
+        assert_eq!(verdict.foul, Some(Foul::WrongBallFirst(2)));
+        assert_eq!(game.shooter, 1);
+        assert_eq!(game.ball_in_hand, Some(BallInHand::Anywhere));
+    }
+
+    #[test]
+    fn test_no_rail_after_contact() {
+        let mut game = game_after_break();
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::Cushion(0),
+            ShotEvent::BallBall(0, 5),
+        ]));
+        assert_eq!(verdict.foul, Some(Foul::NoRailAfterContact));
+        assert_eq!(game.ball_in_hand, Some(BallInHand::Anywhere));
+    }
+
+    #[test]
+    fn test_called_ball_missed_does_not_count() {
+        let mut game = game_after_break();
+        let verdict = game.play(Some(Call { ball: 5, pocket: 0 }), &outcome(vec![
+            ShotEvent::BallBall(0, 5),
+            ShotEvent::Pocket(5, 4),
+        ]));
+        assert_eq!(verdict.foul, None);
+        assert!(verdict.turn_passes);
+        assert_eq!(game.phase, Phase::Open);
+    }
+
+    #[test]
+    fn test_early_eight_loses() {
+        let mut game = game_after_break();
+        game.play(Some(Call { ball: 8, pocket: 0 }), &outcome(vec![
+            ShotEvent::BallBall(0, 8),
+            ShotEvent::Pocket(8, 0),
+        ]));
+        assert_eq!(game.winner(), Some(1));
+    }
+
+    fn game_on_eight() -> Game {
+        let mut game = game_after_break();
+        game.play(None, &outcome(vec![ShotEvent::BallBall(0, 12), ShotEvent::Pocket(12, 1)]));
+        game.on_table.retain(|&ball| Group::of(ball) != Some(Group::Stripes));
+        game
+    }
+
+    #[test]
+    fn test_called_eight_wins() {
+        let mut game = game_on_eight();
+        assert!(game.is_on_eight(0));
+        game.play(Some(Call { ball: 8, pocket: 5 }), &outcome(vec![
+            ShotEvent::BallBall(0, 8),
+            ShotEvent::Pocket(8, 5),
+        ]));
+        assert_eq!(game.winner(), Some(0));
+    }
+
+    #[test]
+    fn test_eight_in_wrong_pocket_loses() {
+        let mut game = game_on_eight();
+        game.play(Some(Call { ball: 8, pocket: 5 }), &outcome(vec![
+            ShotEvent::BallBall(0, 8),
+            ShotEvent::Pocket(8, 4),
+        ]));
+        assert_eq!(game.winner(), Some(1));
+    }
+
+    #[test]
+    fn test_eight_with_scratch_loses() {
+        let mut game = game_on_eight();
+        game.play(Some(Call { ball: 8, pocket: 5 }), &outcome(vec![
+            ShotEvent::BallBall(0, 8),
+            ShotEvent::Pocket(8, 5),
+            ShotEvent::Pocket(0, 2),
+        ]));
+        assert_eq!(game.winner(), Some(1));
+    }
+}
@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+
+// Rules engines for the games that can be played on the simulated tables.
+// They never look at the simulation directly. Each shot is summarized as a
+// ShotOutcome, which is derived from Simulator::events, and the games are
+// state machines that consume one ShotOutcome per shot.
+
+use jlib::{Ball, SimulationEvent, Simulator, WorldConf};
+
+pub mod eight_ball;
+pub mod rack;
+pub mod rotation;
+pub mod snooker;
+pub mod three_cushion;
+
+// The rules refer to balls by number. The cue ball is always number 0.
+pub const CUE_BALL: u32 = 0;
+
+pub fn other_player(player: usize) -> usize {
+    1 - player
+}
+
+// A table with its balls set up for a game. numbers[i] is the number of
+// balls[i].
+#[derive(Clone)]
+pub struct Scene {
+    pub world_conf: WorldConf,
+    pub balls: Vec<Ball>,
+    pub numbers: Vec<u32>,
+}
+
+impl Scene {
+    pub fn into_simulator(self, ts: f64) -> (Simulator, Vec<u32>) {
+        (Simulator::new(self.balls, self.world_conf, ts), self.numbers)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShotEvent {
+    // The order of the two numbers carries no meaning.
+    BallBall(u32, u32),
+    Cushion(u32),
+    // The second field is the index of the pocket in Table::pockets.
+    Pocket(u32, usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct ShotOutcome {
+    pub events: Vec<ShotEvent>,
+}
+
+impl ShotOutcome {
+    pub fn new(events: Vec<ShotEvent>) -> Self {
+        ShotOutcome { events: events }
+    }
+
+    // numbers[i] is the number of the ball at index i of Simulator::balls.
+    // Only the events of a single shot should be passed in.
+    pub fn from_simulation_events(events: &[SimulationEvent], numbers: &[u32]) -> Self {
+        let mut shot_events = Vec::new();
+        for event in events.iter() {
+            match *event {
+                SimulationEvent::BallBall { i, j, .. } =>
+                    shot_events.push(ShotEvent::BallBall(numbers[i], numbers[j])),
+                SimulationEvent::BallCushion { i, .. } =>
+                    shot_events.push(ShotEvent::Cushion(numbers[i])),
+                SimulationEvent::BallPocket { i, pocket, .. } =>
+                    shot_events.push(ShotEvent::Pocket(numbers[i], pocket)),
+                SimulationEvent::BallCloth { .. } => {}
+            }
+        }
+        ShotOutcome::new(shot_events)
+    }
+
+    // Position in events of the first contact of the cue ball with another
+    // ball, together with the number of that ball.
+    fn first_contact_event(&self) -> Option<(usize, u32)> {
+        for (k, event) in self.events.iter().enumerate() {
+            if let ShotEvent::BallBall(a, b) = *event {
+                if a == CUE_BALL {
+                    return Some((k, b));
+                }
+                if b == CUE_BALL {
+                    return Some((k, a));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn first_contact(&self) -> Option<u32> {
+        self.first_contact_event().map(|(_, ball)| ball)
+    }
+
+    // Whether any ball, cue ball included, touched a cushion after the cue
+    // ball's first contact.
+    pub fn rail_after_contact(&self) -> bool {
+        match self.first_contact_event() {
+            Some((k, _)) => self.events[k+1..].iter().any(|event| match *event {
+                ShotEvent::Cushion(_) => true,
+                _ => false,
+            }),
+            None => false,
+        }
+    }
+
+    // Object balls pocketed during the shot, in the order they dropped.
+    pub fn pocketed(&self) -> Vec<u32> {
+        self.events.iter().filter_map(|event| match *event {
+            ShotEvent::Pocket(ball, _) if ball != CUE_BALL => Some(ball),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn pocket_of(&self, ball: u32) -> Option<usize> {
+        self.events.iter().filter_map(|event| match *event {
+            ShotEvent::Pocket(b, pocket) if b == ball => Some(pocket),
+            _ => None,
+        }).next()
+    }
+
+    pub fn is_scratch(&self) -> bool {
+        self.pocket_of(CUE_BALL).is_some()
+    }
+
+    // Number of distinct object balls that touched a cushion.
+    pub fn object_balls_to_cushion(&self) -> usize {
+        let mut balls: Vec<u32> = self.events.iter().filter_map(|event| match *event {
+            ShotEvent::Cushion(ball) if ball != CUE_BALL => Some(ball),
+            _ => None,
+        }).collect();
+        balls.sort();
+        balls.dedup();
+        balls.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jlib::{Cushion, SimulationEvent};
+    use rules::{ShotEvent, ShotOutcome};
+
+    #[test]
+    fn test_from_simulation_events() {
+        let events = vec![
+            SimulationEvent::BallCloth { t: 0.1, i: 2 },
+            SimulationEvent::BallBall { t: 0.2, i: 2, j: 0 },
+            SimulationEvent::BallCushion { t: 0.3, i: 0, cushion: Cushion::Foot },
+            SimulationEvent::BallPocket { t: 0.4, i: 1, pocket: 3 },
+        ];
+        let outcome = ShotOutcome::from_simulation_events(&events, &[5, 9, 0]);
+
+        assert_eq!(outcome.events, vec![
+            ShotEvent::BallBall(0, 5),
+            ShotEvent::Cushion(5),
+            ShotEvent::Pocket(9, 3),
+        ]);
+        assert_eq!(outcome.first_contact(), Some(5));
+        assert!(outcome.rail_after_contact());
+        assert_eq!(outcome.pocketed(), vec![9]);
+        assert!(!outcome.is_scratch());
+    }
+
+    #[test]
+    fn test_rail_before_contact_does_not_count() {
+        let outcome = ShotOutcome::new(vec![
+            ShotEvent::Cushion(0),
+            ShotEvent::BallBall(0, 3),
+        ]);
+        assert!(!outcome.rail_after_contact());
+    }
+}
@@ -0,0 +1,264 @@
+// Racks for the games in rules. Each builder places the object balls as its
+// game prescribes and orders the rest at random. The apex ball sits on
+// RackConf::apex and the rows extend towards positive x, that is towards the
+// foot cushion.
+
+use rand::Rng;
+
+use geometry::JVector3;
+use jlib::{Ball, WorldConf, consts};
+use rules::{Scene, CUE_BALL};
+use rules::eight_ball::EIGHT_BALL;
+use rules::snooker::{FIRST_RED, N_REDS};
+
+pub const DEFAULT_GAP: f64 = 0.0001;
+
+#[derive(Clone)]
+pub struct RackConf {
+    pub apex: JVector3,
+    pub ball_radius: f64,
+    // Distance between neighbouring balls. Zero gives a perfectly tight rack
+    // with every ball frozen to its neighbours.
+    pub gap: f64,
+}
+
+impl RackConf {
+    // Apex ball on the foot spot of a pool table.
+    pub fn pool() -> Self {
+        RackConf {
+            apex: JVector3::new(consts::POOL_TABLE_LENGTH / 4., 0., consts::POOL_BALL_RADIUS),
+            ball_radius: consts::POOL_BALL_RADIUS,
+            gap: DEFAULT_GAP,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Rack {
+    pub balls: Vec<Ball>,
+    // numbers[i] is the number of balls[i].
+    pub numbers: Vec<u32>,
+}
+
+impl Rack {
+    // Puts the cue ball in front of the rack, at index 0.
+    pub fn with_cue_ball(mut self, pos: JVector3) -> Self {
+        self.balls.insert(0, Ball::at_rest(pos));
+        self.numbers.insert(0, CUE_BALL);
+        self
+    }
+
+    pub fn into_scene(self, world_conf: WorldConf) -> Scene {
+        Scene { world_conf: world_conf, balls: self.balls, numbers: self.numbers }
+    }
+}
+
+// Slot positions of a rack with the given number of balls per row.
+fn slots(conf: &RackConf, rows: &[usize]) -> Vec<JVector3> {
+    let d = 2. * conf.ball_radius + conf.gap;
+    let mut slots = Vec::new();
+    for (row, &n) in rows.iter().enumerate() {
+        for k in 0..n {
+            slots.push(conf.apex + JVector3::new(
+                row as f64 * d * 3f64.sqrt() / 2.,
+                (k as f64 - (n as f64 - 1.) / 2.) * d,
+                0.,
+            ));
+        }
+    }
+    slots
+}
+
+const TRIANGLE: [usize; 5] = [1, 2, 3, 4, 5];
+const DIAMOND: [usize; 5] = [1, 2, 3, 2, 1];
+
+// Slot indices within TRIANGLE and DIAMOND.
+const APEX: usize = 0;
+const TRIANGLE_CENTRE: usize = 4;
+const TRIANGLE_BACK_CORNERS: [usize; 2] = [10, 14];
+const DIAMOND_CENTRE: usize = 4;
+
+// numbers are given in slot order.
+fn place(conf: &RackConf, rows: &[usize], numbers: Vec<u32>) -> Rack {
+    let balls = slots(conf, rows).into_iter().map(Ball::at_rest).collect();
+    Rack { balls: balls, numbers: numbers }
+}
+
+// Puts the fixed balls in their slots and the shuffled rest in the others.
+fn fill<R: Rng>(
+    conf: &RackConf,
+    rows: &[usize],
+    fixed: &[(usize, u32)],
+    mut rest: Vec<u32>,
+    rng: &mut R,
+) -> Rack {
+    rng.shuffle(&mut rest);
+    let n: usize = rows.iter().sum();
+    let numbers = (0..n).map(|k| match fixed.iter().find(|&&(slot_k, _)| slot_k == k) {
+        Some(&(_, number)) => number,
+        None => rest.pop().expect("fill called with too few balls for the rack"),
+    }).collect();
+    place(conf, rows, numbers)
+}
+
+// Moves each ball of the rack by up to amount in a random direction on the
+// cloth, to imitate a rack that is not perfectly tight. Two neighbours close
+// in by at most 2 amount, so keep amount below RackConf::gap / 2 to avoid
+// overlapping balls. A tight rack, with a gap of 0, takes an amount of 0.
+pub fn jitter<R: Rng>(rack: &mut Rack, amount: f64, rng: &mut R) {
+    if amount <= 0. {
+        return;
+    }
+    for ball in rack.balls.iter_mut() {
+        // Uniform over the disc of radius amount.
+        let r = amount * rng.gen_range(0., 1f64).sqrt();
+        let angle = rng.gen_range(0., 2. * consts::PI);
+        ball.pos.x += r * angle.cos();
+        ball.pos.y += r * angle.sin();
+    }
+}
+
+// Eight in the centre, a solid in one back corner and a stripe in the other.
+pub fn eight_ball<R: Rng>(conf: &RackConf, rng: &mut R) -> Rack {
+    let mut solids: Vec<u32> = (1..8).collect();
+    let mut stripes: Vec<u32> = (9..16).collect();
+    rng.shuffle(&mut solids);
+    rng.shuffle(&mut stripes);
+    let mut corners = [solids.pop().unwrap(), stripes.pop().unwrap()];
+    if rng.gen() {
+        corners.swap(0, 1);
+    }
+    solids.extend(stripes);
+    fill(conf, &TRIANGLE, &[
+        (TRIANGLE_CENTRE, EIGHT_BALL),
+        (TRIANGLE_BACK_CORNERS[0], corners[0]),
+        (TRIANGLE_BACK_CORNERS[1], corners[1]),
+    ], solids, rng)
+}
+
+// One on the apex and nine in the centre.
+pub fn nine_ball<R: Rng>(conf: &RackConf, rng: &mut R) -> Rack {
+    fill(conf, &DIAMOND, &[(APEX, 1), (DIAMOND_CENTRE, 9)], (2..9).collect(), rng)
+}
+
+// One on the apex and ten in the centre of the third row.
+pub fn ten_ball<R: Rng>(conf: &RackConf, rng: &mut R) -> Rack {
+    fill(conf, &[1, 2, 3, 4], &[(APEX, 1), (TRIANGLE_CENTRE, 10)], (2..10).collect(), rng)
+}
+
+// Fifteen on the apex, one and five in the back corners.
+pub fn straight_pool<R: Rng>(conf: &RackConf, rng: &mut R) -> Rack {
+    fill(conf, &TRIANGLE, &[
+        (APEX, 15),
+        (TRIANGLE_BACK_CORNERS[0], 1),
+        (TRIANGLE_BACK_CORNERS[1], 5),
+    ], (2..15).filter(|&ball| ball != 5).collect(), rng)
+}
+
+// The fifteen reds. The apex should be just behind the pink spot. The reds
+// are interchangeable so there is nothing to order at random.
+pub fn snooker(conf: &RackConf) -> Rack {
+    place(conf, &TRIANGLE, (FIRST_RED..FIRST_RED+N_REDS).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, prng::XorShiftRng};
+
+    use geometry::JVector3;
+    use rules::eight_ball::Group;
+    use rules::rack::{Rack, RackConf, eight_ball, jitter, nine_ball, straight_pool, ten_ball};
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([7; 16])
+    }
+
+    fn assert_no_overlaps(rack: &Rack, radius: f64) {
+        for i in 0..rack.balls.len() {
+            for j in i+1..rack.balls.len() {
+                assert!((rack.balls[i].pos - rack.balls[j].pos).norm() >= 2. * radius);
+            }
+        }
+    }
+
+    fn number_at(rack: &Rack, pos: &JVector3) -> u32 {
+        let i = rack.balls.iter().position(|ball| (ball.pos - pos).norm() < 1e-9).unwrap();
+        rack.numbers[i]
+    }
+
+    #[test]
+    fn test_eight_ball() {
+        let conf = RackConf::pool();
+        let mut rng = rng();
+        for _ in 0..20 {
+            let rack = eight_ball(&conf, &mut rng);
+            let mut numbers = rack.numbers.clone();
+            numbers.sort();
+            assert_eq!(numbers, (1..16).collect::<Vec<u32>>());
+            assert_eq!(rack.numbers[4], 8);
+            let corners = [Group::of(rack.numbers[10]), Group::of(rack.numbers[14])];
+            assert!(corners[0] != corners[1]);
+            assert_no_overlaps(&rack, conf.ball_radius);
+        }
+    }
+
+    #[test]
+    fn test_nine_ball() {
+        let conf = RackConf::pool();
+        let rack = nine_ball(&conf, &mut rng());
+        assert_eq!(rack.numbers.len(), 9);
+        assert_eq!(number_at(&rack, &conf.apex), 1);
+        assert_eq!(rack.numbers[4], 9);
+        // The nine is in the middle of the diamond, on the centre line.
+        assert_eq!(rack.balls[4].pos.y, 0.);
+    }
+
+    #[test]
+    fn test_ten_ball_and_straight_pool() {
+        let conf = RackConf::pool();
+        let rack = ten_ball(&conf, &mut rng());
+        assert_eq!(rack.numbers.len(), 10);
+        assert_eq!((rack.numbers[0], rack.numbers[4]), (1, 10));
+
+        let rack = straight_pool(&conf, &mut rng());
+        assert_eq!((rack.numbers[0], rack.numbers[10], rack.numbers[14]), (15, 1, 5));
+    }
+
+    #[test]
+    fn test_seeded_racks_repeat() {
+        let conf = RackConf::pool();
+        assert_eq!(eight_ball(&conf, &mut rng()).numbers, eight_ball(&conf, &mut rng()).numbers);
+    }
+
+    #[test]
+    fn test_gap_and_jitter() {
+        let mut conf = RackConf::pool();
+        conf.gap = 0.;
+        let rack = nine_ball(&conf, &mut rng());
+        let d = (rack.balls[0].pos - rack.balls[1].pos).norm();
+        assert!((d - 2. * conf.ball_radius).abs() < 1e-12);
+
+        conf.gap = 0.002;
+        let mut rack = eight_ball(&conf, &mut rng());
+        jitter(&mut rack, 0.0009, &mut rng());
+        assert_no_overlaps(&rack, conf.ball_radius);
+        assert!(rack.balls[0].pos != conf.apex);
+
+        // Whatever the seed, as long as amount is below gap / 2.
+        for seed in 0..50 {
+            let mut rack = eight_ball(&conf, &mut rng());
+            jitter(&mut rack, 0.000999, &mut XorShiftRng::from_seed([seed; 16]));
+            assert_no_overlaps(&rack, conf.ball_radius);
+        }
+
+        conf.gap = 0.;
+        let mut tight = nine_ball(&conf, &mut rng());
+        let before: Vec<JVector3> = tight.balls.iter().map(|ball| ball.pos).collect();
+        jitter(&mut tight, 0., &mut rng());
+        assert!(tight.balls.iter().zip(before.iter()).all(|(ball, pos)| ball.pos == *pos));
+
+        let rack = rack.with_cue_ball(JVector3::new(-0.6, 0., conf.ball_radius));
+        assert_eq!(rack.numbers[0], 0);
+        assert_eq!(rack.balls.len(), 16);
+    }
+}
@@ -0,0 +1,376 @@
+// WPA nine-ball and ten-ball. Both are rotation games: the lowest-numbered
+// ball on the table has to be contacted first, and the game is won by
+// legally pocketing the highest-numbered ball. Players are 0 and 1.
+
+use rules::{ShotOutcome, other_player};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    NineBall,
+    TenBall,
+}
+
+impl Variant {
+    pub fn game_ball(self) -> u32 {
+        match self {
+            Variant::NineBall => 9,
+            Variant::TenBall => 10,
+        }
+    }
+
+    // In ten-ball every shot after the break is called.
+    pub fn is_call_shot(self) -> bool {
+        self == Variant::TenBall
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Break,
+    // The shot after the break may be played as a push out.
+    PushOutAvailable,
+    // The opponent of the player who pushed out either plays or passes.
+    PassAvailable,
+    Play,
+    Over { winner: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Foul {
+    Scratch,
+    NoContact,
+    WrongBallFirst(u32),
+    NoRailAfterContact,
+    // Fewer than four object balls reached a cushion and none was pocketed.
+    IllegalBreak,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Call {
+    pub ball: u32,
+    pub pocket: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub foul: Option<Foul>,
+    pub turn_passes: bool,
+    // The game ball went down without winning and has to go back on its spot.
+    pub respot_game_ball: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub variant: Variant,
+    pub phase: Phase,
+    pub shooter: usize,
+    pub on_table: Vec<u32>,
+    // The incoming player may place the cue ball anywhere.
+    pub ball_in_hand: bool,
+    // Fouls committed by each player on successive shots.
+    pub consecutive_fouls: [u32; 2],
+}
+
+impl Game {
+    pub fn new(variant: Variant, breaker: usize) -> Self {
+        Game {
+            variant: variant,
+            phase: Phase::Break,
+            shooter: breaker,
+            on_table: (1..variant.game_ball()+1).collect(),
+            ball_in_hand: false,
+            consecutive_fouls: [0, 0],
+        }
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        match self.phase {
+            Phase::Over { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    pub fn lowest_on_table(&self) -> Option<u32> {
+        self.on_table.iter().cloned().min()
+    }
+
+    // Plays a push out. The cue ball does not have to contact any ball and
+    // nothing has to reach a cushion, but a scratch is still a foul. Balls
+    // pocketed on a push out do not count.
+    pub fn push_out(&mut self, outcome: &ShotOutcome) -> Verdict {
+        if self.phase != Phase::PushOutAvailable {
+            panic!("push_out called when no push out is available");
+        }
+        self.ball_in_hand = false;
+
+        let pocketed = outcome.pocketed();
+        self.on_table.retain(|ball| !pocketed.contains(ball));
+        let respot_game_ball = self.respot_game_ball_if_pocketed(&pocketed);
+
+        let foul = if outcome.is_scratch() { Some(Foul::Scratch) } else { None };
+        self.phase = if foul.is_some() { Phase::Play } else { Phase::PassAvailable };
+        self.finish(Verdict { foul: foul, turn_passes: true, respot_game_ball: respot_game_ball })
+    }
+
+    // After a push out the incoming player may hand the table back.
+    pub fn pass(&mut self) {
+        if self.phase != Phase::PassAvailable {
+            panic!("pass called when passing is not allowed");
+        }
+        self.phase = Phase::Play;
+        self.shooter = other_player(self.shooter);
+    }
+
+    // call is only looked at in ten-ball, where it is required after the
+    // break.
+    pub fn play(&mut self, call: Option<Call>, outcome: &ShotOutcome) -> Verdict {
+        if let Phase::Over { .. } = self.phase {
+            panic!("play called on a finished rotation game");
+        }
+        self.ball_in_hand = false;
+
+        let lowest = self.lowest_on_table();
+        let pocketed = outcome.pocketed();
+        self.on_table.retain(|ball| !pocketed.contains(ball));
+
+        let is_break = self.phase == Phase::Break;
+        let foul = if outcome.is_scratch() {
+            Some(Foul::Scratch)
+        }
+        else {
+            match outcome.first_contact() {
+                None => Some(Foul::NoContact),
+                Some(ball) if Some(ball) != lowest => Some(Foul::WrongBallFirst(ball)),
+                Some(_) if is_break && pocketed.is_empty() && outcome.object_balls_to_cushion() < 4 =>
+                    Some(Foul::IllegalBreak),
+                Some(_) if pocketed.is_empty() && !outcome.rail_after_contact() =>
+                    Some(Foul::NoRailAfterContact),
+                Some(_) => None,
+            }
+        };
+
+        self.phase = if is_break { Phase::PushOutAvailable } else { Phase::Play };
+
+        let game_ball = self.variant.game_ball();
+        let game_ball_pocketed = pocketed.contains(&game_ball);
+
+        if foul.is_none() {
+            let called_correctly = !self.variant.is_call_shot() || is_break || match call {
+                Some(call) => outcome.pocket_of(call.ball) == Some(call.pocket),
+                None => false,
+            };
+
+            // In nine-ball the nine wins however it goes down, on the break
+            // or in a combination. In ten-ball it has to be the called ball.
+            let game_ball_wins = game_ball_pocketed && match self.variant {
+                Variant::NineBall => true,
+                Variant::TenBall => !is_break && call.map(|call| call.ball) == Some(game_ball) && called_correctly,
+            };
+            if game_ball_wins {
+                self.phase = Phase::Over { winner: self.shooter };
+                return self.finish(Verdict { foul: None, turn_passes: false, respot_game_ball: false });
+            }
+
+            let respot_game_ball = self.respot_game_ball_if_pocketed(&pocketed);
+            let scored = !pocketed.is_empty() && called_correctly;
+            return self.finish(Verdict {
+                foul: None,
+                turn_passes: !scored,
+                respot_game_ball: respot_game_ball,
+            });
+        }
+
+        let respot_game_ball = self.respot_game_ball_if_pocketed(&pocketed);
+        self.finish(Verdict { foul: foul, turn_passes: true, respot_game_ball: respot_game_ball })
+    }
+
+    fn respot_game_ball_if_pocketed(&mut self, pocketed: &[u32]) -> bool {
+        let game_ball = self.variant.game_ball();
+        if pocketed.contains(&game_ball) {
+            self.on_table.push(game_ball);
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    // Applies the consequences common to every shot: fouls give ball in hand
+    // and count towards the three-foul rule, and the turn may pass.
+    fn finish(&mut self, verdict: Verdict) -> Verdict {
+        if verdict.foul.is_some() {
+            self.consecutive_fouls[self.shooter] += 1;
+            if self.consecutive_fouls[self.shooter] >= 3 {
+                self.phase = Phase::Over { winner: other_player(self.shooter) };
+                return verdict;
+            }
+            self.ball_in_hand = true;
+        }
+        else {
+            self.consecutive_fouls[self.shooter] = 0;
+        }
+
+        if verdict.turn_passes {
+            self.shooter = other_player(self.shooter);
+        }
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rules::{ShotEvent, ShotOutcome};
+    use rules::rotation::{Call, Foul, Game, Phase, Variant};
+
+    fn outcome(events: Vec<ShotEvent>) -> ShotOutcome {
+        ShotOutcome::new(events)
+    }
+
+    fn miss(ball: u32) -> ShotOutcome {
+        // Legal contact, nothing pocketed.
+        outcome(vec![ShotEvent::BallBall(0, ball), ShotEvent::Cushion(ball)])
+    }
+
+    fn foul() -> ShotOutcome {
+        outcome(vec![ShotEvent::Cushion(0)])
+    }
+
+    fn game_after_break(variant: Variant) -> Game {
+        let mut game = Game::new(variant, 0);
+        game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(4, 0),
+        ]));
+        game
+    }
+
+    #[test]
+    fn test_nine_on_break_wins() {
+        let mut game = Game::new(Variant::NineBall, 0);
+        game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(9, 2),
+        ]));
+        assert_eq!(game.winner(), Some(0));
+    }
+
+    #[test]
+    fn test_ten_on_break_is_respotted() {
+        let mut game = Game::new(Variant::TenBall, 0);
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(10, 2),
+        ]));
+        assert!(verdict.respot_game_ball);
+        assert!(game.on_table.contains(&10));
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn test_lowest_ball_first() {
+        let mut game = game_after_break(Variant::NineBall);
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 3),
+            ShotEvent::Pocket(3, 1),
+        ]));
+        assert_eq!(verdict.foul, Some(Foul::WrongBallFirst(3)));
+        assert!(game.ball_in_hand);
+        assert_eq!(game.shooter, 1);
+    }
+
+    #[test]
+    fn test_nine_ball_combination_wins() {
+        let mut game = game_after_break(Variant::NineBall);
+        game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::BallBall(1, 9),
+            ShotEvent::Pocket(9, 3),
+        ]));
+        assert_eq!(game.winner(), Some(0));
+    }
+
+    #[test]
+    fn test_nine_on_foul_is_respotted() {
+        let mut game = game_after_break(Variant::NineBall);
+        let verdict = game.play(None, &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::BallBall(1, 9),
+            ShotEvent::Pocket(9, 3),
+            ShotEvent::Pocket(0, 4),
+        ]));
+        assert_eq!(verdict.foul, Some(Foul::Scratch));
+        assert!(verdict.respot_game_ball);
+        assert_eq!(game.winner(), None);
+        assert!(game.on_table.contains(&9));
+    }
+
+    #[test]
+    fn test_push_out_and_pass() {
+        let mut game = Game::new(Variant::NineBall, 0);
+        game.play(None, &miss(1));
+        assert_eq!(game.phase, Phase::PushOutAvailable);
+        assert_eq!(game.shooter, 1);
+
+        // No contact is fine on a push out.
+        let verdict = game.push_out(&outcome(vec![]));
+        assert_eq!(verdict.foul, None);
+        assert_eq!(game.shooter, 0);
+        assert_eq!(game.phase, Phase::PassAvailable);
+
+        game.pass();
+        assert_eq!(game.shooter, 1);
+        assert_eq!(game.phase, Phase::Play);
+    }
+
+    #[test]
+    fn test_three_fouls_lose() {
+        let mut game = game_after_break(Variant::NineBall);
+        for _ in 0..2 {
+            game.play(None, &foul());
+            game.play(None, &miss(1));
+        }
+        assert_eq!(game.consecutive_fouls[0], 2);
+        game.play(None, &foul());
+        assert_eq!(game.winner(), Some(1));
+    }
+
+    #[test]
+    fn test_legal_shot_resets_fouls() {
+        let mut game = game_after_break(Variant::NineBall);
+        game.play(None, &foul());
+        game.play(None, &miss(1));
+        game.play(None, &miss(1));
+        assert_eq!(game.consecutive_fouls[0], 0);
+    }
+
+    #[test]
+    fn test_ten_ball_uncalled_ball_passes_turn() {
+        let mut game = game_after_break(Variant::TenBall);
+        let verdict = game.play(Some(Call { ball: 1, pocket: 0 }), &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::Pocket(1, 5),
+        ]));
+        assert_eq!(verdict.foul, None);
+        assert!(verdict.turn_passes);
+    }
+
+    #[test]
+    fn test_ten_ball_needs_called_ten() {
+        let mut game = game_after_break(Variant::TenBall);
+        let verdict = game.play(Some(Call { ball: 1, pocket: 0 }), &outcome(vec![
+            ShotEvent::BallBall(0, 1),
+            ShotEvent::BallBall(1, 10),
+            ShotEvent::Pocket(10, 0),
+        ]));
+        assert!(verdict.respot_game_ball);
+        assert_eq!(game.winner(), None);
+
+        game.on_table = vec![10];
+        game.shooter = 0;
+        game.play(Some(Call { ball: 10, pocket: 2 }), &outcome(vec![
+            ShotEvent::BallBall(0, 10),
+            ShotEvent::Pocket(10, 2),
+        ]));
+        assert_eq!(game.winner(), Some(0));
+    }
+}
@@ -0,0 +1,583 @@
+// Snooker, scored frame by frame. Players are 0 and 1.
+//
+// The colours are numbered by their value, from YELLOW (2) to BLACK (7). The
+// fifteen reds are numbered from FIRST_RED upwards; the rules do not tell
+// them apart. The baulk end of the table is at negative x.
+
+use geometry::JVector3;
+use jlib::{Ball, WorldConf, consts};
+use rules::{Scene, ShotOutcome, CUE_BALL, other_player};
+use rules::rack::{self, RackConf};
+
+pub const YELLOW: u32 = 2;
+pub const GREEN: u32 = 3;
+pub const BROWN: u32 = 4;
+pub const BLUE: u32 = 5;
+pub const PINK: u32 = 6;
+pub const BLACK: u32 = 7;
+pub const FIRST_RED: u32 = 8;
+pub const N_REDS: u32 = 15;
+
+pub const MIN_FOUL_PENALTY: u32 = 4;
+
+// Distances of the markings from the cushion noses.
+pub const BAULK_LINE_DISTANCE: f64 = 0.737;
+pub const D_RADIUS: f64 = 0.292;
+pub const BLACK_SPOT_DISTANCE: f64 = 0.324;
+
+pub fn is_red(ball: u32) -> bool {
+    ball >= FIRST_RED
+}
+
+pub fn value_of(ball: u32) -> u32 {
+    if is_red(ball) { 1 } else { ball }
+}
+
+pub fn spot_of(colour: u32) -> JVector3 {
+    let hl = consts::SNOOKER_TABLE_LENGTH / 2.;
+    let baulk_x = -hl + BAULK_LINE_DISTANCE;
+    let (x, y) = match colour {
+        YELLOW => (baulk_x, -D_RADIUS),
+        GREEN => (baulk_x, D_RADIUS),
+        BROWN => (baulk_x, 0.),
+        BLUE => (0., 0.),
+        PINK => (hl / 2., 0.),
+        BLACK => (hl - BLACK_SPOT_DISTANCE, 0.),
+        _ => panic!("spot_of called for ball {} which is not a colour", colour),
+    };
+    JVector3::new(x, y, consts::SNOOKER_BALL_RADIUS)
+}
+
+fn is_free(pos: &JVector3, occupied: &[JVector3], radius: f64) -> bool {
+    occupied.iter().all(|other| {
+        JVector3::new(pos.x - other.x, pos.y - other.y, 0.).norm() >= 2. * radius
+    })
+}
+
+// Where a colour goes back on the table. occupied holds the positions of the
+// balls currently on the table. If its own spot is taken the colour goes on
+// the highest-valued free spot. If every spot is taken it goes as near as
+// possible to its own spot, on the line through the spot parallel to the
+// long cushions: towards the top cushion if there is room and towards baulk
+// otherwise. For yellow and green that line is off the centre line.
+pub fn respot_position(colour: u32, occupied: &[JVector3], radius: f64) -> JVector3 {
+    let own = spot_of(colour);
+    if is_free(&own, occupied, radius) {
+        return own;
+    }
+
+    for spot_colour in (YELLOW..BLACK+1).rev() {
+        let spot = spot_of(spot_colour);
+        if is_free(&spot, occupied, radius) {
+            return spot;
+        }
+    }
+
+    let hl = consts::SNOOKER_TABLE_LENGTH / 2.;
+    let step = radius / 10.;
+    for &direction in [1., -1.].iter() {
+        let mut pos = own;
+        while pos.x.abs() + radius <= hl {
+            if is_free(&pos, occupied, radius) {
+                return pos;
+            }
+            pos.x += direction * step;
+        }
+    }
+
+    panic!("respot_position found no room for colour {}", colour);
+}
+
+pub fn default_scene() -> Scene {
+    let r = consts::SNOOKER_BALL_RADIUS;
+    let world_conf = WorldConf::snooker();
+
+    let baulk = spot_of(BROWN);
+    let mut balls = vec![Ball::at_rest(JVector3::new(baulk.x - D_RADIUS / 2., -D_RADIUS / 2., r))];
+    let mut numbers = vec![CUE_BALL];
+
+    for colour in YELLOW..BLACK+1 {
+        balls.push(Ball::at_rest(spot_of(colour)));
+        numbers.push(colour);
+    }
+
+    // Reds in a triangle whose apex nearly touches the pink.
+    let reds = rack::snooker(&RackConf {
+        apex: spot_of(PINK) + JVector3::new(2. * r + 0.001, 0., 0.),
+        ball_radius: r,
+        gap: rack::DEFAULT_GAP,
+    });
+    balls.extend(reds.balls);
+    numbers.extend(reds.numbers);
+
+    Scene { world_conf: world_conf, balls: balls, numbers: numbers }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BallOn {
+    Red,
+    // Any colour, to be nominated.
+    Colour,
+    // The reds are gone and the colours are taken in ascending order.
+    Clearance(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Foul {
+    InOff,
+    NoContact,
+    WrongBallFirst(u32),
+    WrongBallPocketed(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub foul: Option<Foul>,
+    // Points scored by the shooter, or awarded to the opponent on a foul.
+    pub points: u32,
+    pub turn_passes: bool,
+    // Colours that have to go back on the table; see respot_position.
+    pub respot: Vec<u32>,
+    // The shooter failed to hit a ball on without needing snookers. The
+    // opponent may have the balls put back and make the offender play again
+    // with replay_after_miss.
+    pub miss: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub shooter: usize,
+    pub ball_on: BallOn,
+    pub on_table: Vec<u32>,
+    pub scores: [u32; 2],
+    pub current_break: u32,
+    pub highest_breaks: [u32; 2],
+    // Completed breaks as (player, points), in the order they were made.
+    pub breaks: Vec<(usize, u32)>,
+    // Set with award_free_ball after a foul that leaves the incoming player
+    // snookered. Applies to the next shot only.
+    pub free_ball: bool,
+    pub winner: Option<usize>,
+    before_miss: Option<(usize, BallOn, Vec<u32>)>,
+}
+
+impl Game {
+    pub fn new(breaker: usize) -> Self {
+        let mut on_table: Vec<u32> = (YELLOW..BLACK+1).collect();
+        on_table.extend(FIRST_RED..FIRST_RED+N_REDS);
+        Game {
+            shooter: breaker,
+            ball_on: BallOn::Red,
+            on_table: on_table,
+            scores: [0, 0],
+            current_break: 0,
+            highest_breaks: [0, 0],
+            breaks: Vec::new(),
+            free_ball: false,
+            winner: None,
+            before_miss: None,
+        }
+    }
+
+    fn reds_on_table(&self) -> bool {
+        self.on_table.iter().any(|&ball| is_red(ball))
+    }
+
+    // nominated is the colour nominated when on a colour, or the ball
+    // nominated as a free ball.
+    fn is_on(&self, ball: u32, nominated: Option<u32>) -> bool {
+        if self.free_ball && nominated == Some(ball) {
+            return true;
+        }
+        match self.ball_on {
+            BallOn::Red => is_red(ball),
+            BallOn::Colour => !is_red(ball) && (nominated.is_none() || nominated == Some(ball)),
+            BallOn::Clearance(colour) => ball == colour,
+        }
+    }
+
+    fn value_on(&self, nominated: Option<u32>) -> u32 {
+        match self.ball_on {
+            BallOn::Red => 1,
+            BallOn::Colour => nominated.map(value_of).unwrap_or(0),
+            BallOn::Clearance(colour) => colour,
+        }
+    }
+
+    fn ball_on_after_turn(&self) -> BallOn {
+        if self.reds_on_table() {
+            BallOn::Red
+        }
+        else {
+            BallOn::Clearance(self.on_table.iter().cloned().filter(|&ball| !is_red(ball)).min().unwrap_or(BLACK))
+        }
+    }
+
+    // The most the shooter could still score from the balls on the table:
+    // every red with the black, the colour after a red, then the colours.
+    fn points_remaining(&self) -> u32 {
+        let reds = self.on_table.iter().filter(|&&ball| is_red(ball)).count() as u32;
+        let colours: u32 = self.on_table.iter().filter(|&&ball| !is_red(ball)).cloned().sum();
+        let colour_on = if self.ball_on == BallOn::Colour { BLACK } else { 0 };
+        reds * (1 + BLACK) + colour_on + colours
+    }
+
+    // Whether player can no longer win the frame without the opponent giving
+    // away points in fouls.
+    fn needs_snookers(&self, player: usize) -> bool {
+        self.scores[other_player(player)] > self.scores[player] + self.points_remaining()
+    }
+
+    pub fn award_free_ball(&mut self) {
+        self.free_ball = true;
+    }
+
+    // Whether every ball on is hidden from the cue ball, that is no ball on
+    // can be hit on both sides by a straight shot. balls holds the numbers and
+    // positions of the balls on the table, the cue ball excluded.
+    pub fn is_snookered(&self, cue: &JVector3, balls: &[(u32, JVector3)], radius: f64) -> bool {
+        let clear = |from: &JVector3, to: &JVector3, target: u32| balls.iter().all(|&(ball, ref pos)| {
+            ball == target || distance_to_segment(pos, from, to) >= 2. * radius
+        });
+
+        !balls.iter().any(|&(ball, ref pos)| {
+            if !self.is_on(ball, None) {
+                return false;
+            }
+            let d = JVector3::new(pos.x - cue.x, pos.y - cue.y, 0.);
+            let perp = JVector3::new(-d.y, d.x, 0.) / d.norm();
+            // The cue ball grazes either side of the ball on when its centre
+            // passes at 2 * radius from the centre of that ball.
+            clear(cue, &(pos + perp * 2. * radius), ball) && clear(cue, &(pos - perp * 2. * radius), ball)
+        })
+    }
+
+    // After a miss, puts the balls back as they were before the foul and has
+    // the offender play again. The penalty stands.
+    pub fn replay_after_miss(&mut self) {
+        match self.before_miss.take() {
+            Some((shooter, ball_on, on_table)) => {
+                self.shooter = shooter;
+                self.ball_on = ball_on;
+                self.on_table = on_table;
+                self.free_ball = false;
+            }
+            None => panic!("replay_after_miss called but the last shot was not a miss"),
+        }
+    }
+
+    pub fn play(&mut self, nominated: Option<u32>, outcome: &ShotOutcome) -> Verdict {
+        if self.winner.is_some() {
+            panic!("play called on a finished snooker frame");
+        }
+
+        let before = (self.shooter, self.ball_on, self.on_table.clone());
+        let needed_snookers = self.needs_snookers(self.shooter);
+        self.before_miss = None;
+
+        let pocketed = outcome.pocketed();
+        let first_contact = outcome.first_contact();
+        // When on a colour without a nomination, the colour hit first is
+        // taken to be the nominated one.
+        let nominated = match (self.ball_on, nominated) {
+            (BallOn::Colour, None) => first_contact.filter(|&ball| !is_red(ball)),
+            _ => nominated,
+        };
+        let free_ball = if self.free_ball { nominated } else { None };
+
+        let foul = if outcome.is_scratch() {
+            Some(Foul::InOff)
+        }
+        else {
+            match first_contact {
+                None => Some(Foul::NoContact),
+                Some(ball) if !self.is_on(ball, nominated) => Some(Foul::WrongBallFirst(ball)),
+                Some(_) => pocketed.iter()
+                    .find(|&&ball| !self.is_on(ball, nominated))
+                    .map(|&ball| Foul::WrongBallPocketed(ball)),
+            }
+        };
+
+        self.on_table.retain(|ball| !pocketed.contains(ball));
+        self.free_ball = false;
+
+        if let Some(foul) = foul {
+            let mut penalty = *[
+                MIN_FOUL_PENALTY,
+                self.value_on(nominated),
+                first_contact.map(value_of).unwrap_or(0),
+            ].iter().max().unwrap();
+            for &ball in pocketed.iter() {
+                penalty = penalty.max(value_of(ball));
+            }
+
+            let last_black = self.ball_on == BallOn::Clearance(BLACK);
+            let respot: Vec<u32> = pocketed.iter().cloned()
+                .filter(|&ball| !is_red(ball) && !last_black)
+                .collect();
+            self.on_table.extend(respot.iter().cloned());
+
+            let offender = self.shooter;
+            self.scores[other_player(offender)] += penalty;
+            self.end_turn();
+
+            // No miss is called on a player who needed snookers before the
+            // stroke or does as a result of it.
+            let miss = match foul {
+                Foul::NoContact | Foul::WrongBallFirst(_) => !needed_snookers && !self.needs_snookers(offender),
+                _ => false,
+            };
+            if miss {
+                self.before_miss = Some(before);
+            }
+            if last_black {
+                self.finish_frame();
+            }
+            return Verdict { foul: Some(foul), points: penalty, turn_passes: true, respot: respot, miss: miss };
+        }
+
+        let value_on = self.value_on(nominated);
+        let points: u32 = pocketed.iter()
+            .map(|&ball| if Some(ball) == free_ball { value_on } else { value_of(ball) })
+            .sum();
+
+        // Colours come back unless they are taken in the clearance. A free
+        // ball always comes back.
+        let respot: Vec<u32> = pocketed.iter().cloned().filter(|&ball| {
+            !is_red(ball) && (Some(ball) == free_ball || self.ball_on == BallOn::Colour)
+        }).collect();
+        self.on_table.extend(respot.iter().cloned());
+
+        if pocketed.is_empty() {
+            self.end_turn();
+            return Verdict { foul: None, points: 0, turn_passes: true, respot: respot, miss: false };
+        }
+
+        self.scores[self.shooter] += points;
+        self.current_break += points;
+
+        self.ball_on = match self.ball_on {
+            BallOn::Red => BallOn::Colour,
+            BallOn::Colour => self.ball_on_after_turn(),
+            BallOn::Clearance(colour) if free_ball.is_some() => BallOn::Clearance(colour),
+            BallOn::Clearance(BLACK) => {
+                self.finish_frame();
+                BallOn::Clearance(BLACK)
+            }
+            BallOn::Clearance(colour) => BallOn::Clearance(colour + 1),
+        };
+
+        Verdict { foul: None, points: points, turn_passes: false, respot: respot, miss: false }
+    }
+
+    fn record_break(&mut self) {
+        if self.current_break > 0 {
+            let shooter = self.shooter;
+            self.breaks.push((shooter, self.current_break));
+            self.highest_breaks[shooter] = self.highest_breaks[shooter].max(self.current_break);
+        }
+        self.current_break = 0;
+    }
+
+    fn end_turn(&mut self) {
+        self.record_break();
+        self.shooter = other_player(self.shooter);
+        self.ball_on = self.ball_on_after_turn();
+    }
+
+    fn finish_frame(&mut self) {
+        self.record_break();
+
+        if self.scores[0] == self.scores[1] {
+            // A tie is settled on a re-spotted black.
+            self.on_table = vec![BLACK];
+            self.ball_on = BallOn::Clearance(BLACK);
+        }
+        else {
+            self.winner = Some(if self.scores[0] > self.scores[1] { 0 } else { 1 });
+        }
+    }
+}
+
+fn distance_to_segment(p: &JVector3, a: &JVector3, b: &JVector3) -> f64 {
+    let ab = JVector3::new(b.x - a.x, b.y - a.y, 0.);
+    let ap = JVector3::new(p.x - a.x, p.y - a.y, 0.);
+    let w = (ap.dot(&ab) / ab.norm_squared()).max(0.).min(1.);
+    (ap - ab * w).norm()
+}
+
+#[cfg(test)]
+mod tests {
+    use geometry::JVector3;
+    use jlib::consts;
+    use rules::{ShotEvent, ShotOutcome};
+    use rules::snooker::{
+        BallOn, Foul, Game, BLACK, BLUE, FIRST_RED, PINK, YELLOW,
+        default_scene, respot_position, spot_of,
+    };
+
+    fn outcome(events: Vec<ShotEvent>) -> ShotOutcome {
+        ShotOutcome::new(events)
+    }
+
+    fn pot(first: u32, pocketed: &[u32]) -> ShotOutcome {
+        let mut events = vec![ShotEvent::BallBall(0, first)];
+        events.extend(pocketed.iter().map(|&ball| ShotEvent::Pocket(ball, 0)));
+        outcome(events)
+    }
+
+    #[test]
+    fn test_default_scene() {
+        let scene = default_scene();
+        assert_eq!(scene.balls.len(), 22);
+        assert_eq!(scene.numbers.len(), 22);
+    }
+
+    #[test]
+    fn test_red_colour_alternation() {
+        let mut game = Game::new(0);
+        game.play(None, &pot(FIRST_RED, &[FIRST_RED]));
+        assert_eq!(game.ball_on, BallOn::Colour);
+
+        let verdict = game.play(Some(BLACK), &pot(BLACK, &[BLACK]));
+        assert_eq!(verdict.respot, vec![BLACK]);
+        assert_eq!(game.ball_on, BallOn::Red);
+        assert_eq!(game.scores, [8, 0]);
+        assert_eq!(game.current_break, 8);
+
+        game.play(None, &outcome(vec![ShotEvent::BallBall(0, FIRST_RED + 1), ShotEvent::Cushion(FIRST_RED + 1)]));
+        assert_eq!(game.shooter, 1);
+        assert_eq!(game.breaks, vec![(0, 8)]);
+        assert_eq!(game.highest_breaks, [8, 0]);
+    }
+
+    #[test]
+    fn test_foul_penalty_by_value() {
+        let mut game = Game::new(0);
+        let verdict = game.play(None, &pot(PINK, &[]));
+        assert_eq!(verdict.foul, Some(Foul::WrongBallFirst(PINK)));
+        assert_eq!(verdict.points, 6);
+        assert_eq!(game.scores, [0, 6]);
+        assert!(verdict.miss);
+
+        let verdict = game.play(None, &pot(FIRST_RED, &[FIRST_RED, 0]));
+        assert_eq!(verdict.foul, Some(Foul::InOff));
+        assert_eq!(verdict.points, 4);
+        assert_eq!(game.scores, [4, 6]);
+    }
+
+    #[test]
+    fn test_potting_colour_on_red_respots_it() {
+        let mut game = Game::new(0);
+        let verdict = game.play(None, &pot(FIRST_RED, &[FIRST_RED, BLUE]));
+        assert_eq!(verdict.foul, Some(Foul::WrongBallPocketed(BLUE)));
+        assert_eq!(verdict.respot, vec![BLUE]);
+        assert!(game.on_table.contains(&BLUE));
+        assert!(!game.on_table.contains(&FIRST_RED));
+    }
+
+    #[test]
+    fn test_miss_and_replay() {
+        let mut game = Game::new(0);
+        game.play(None, &outcome(vec![ShotEvent::Cushion(0)]));
+        assert_eq!(game.shooter, 1);
+        game.replay_after_miss();
+        assert_eq!(game.shooter, 0);
+        assert_eq!(game.scores, [0, 4]);
+    }
+
+    #[test]
+    fn test_no_miss_when_snookers_are_needed() {
+        // Pink and black left, 13 points.
+        let clearance = |scores: [u32; 2]| {
+            let mut game = Game::new(0);
+            game.on_table = vec![PINK, BLACK];
+            game.ball_on = BallOn::Clearance(PINK);
+            game.scores = scores;
+            game
+        };
+        let missed = || outcome(vec![ShotEvent::Cushion(0)]);
+
+        let mut game = clearance([0, 0]);
+        assert!(game.play(None, &missed()).miss);
+        game.replay_after_miss();
+
+        // Snookers needed before the stroke.
+        let verdict = clearance([0, 20]).play(None, &missed());
+        assert_eq!(verdict.foul, Some(Foul::NoContact));
+        assert!(!verdict.miss);
+
+        // Snookers needed only after the penalty of 7 for hitting the black.
+        let verdict = clearance([0, 10]).play(None, &pot(BLACK, &[]));
+        assert_eq!(verdict.foul, Some(Foul::WrongBallFirst(BLACK)));
+        assert!(!verdict.miss);
+        let verdict = clearance([0, 5]).play(None, &pot(BLACK, &[]));
+        assert!(verdict.miss);
+    }
+
+    #[test]
+    fn test_free_ball() {
+        let mut game = Game::new(0);
+        game.play(None, &pot(BLUE, &[]));
+        game.award_free_ball();
+        let verdict = game.play(Some(YELLOW), &pot(YELLOW, &[YELLOW]));
+        assert_eq!(verdict.foul, None);
+        assert_eq!(verdict.points, 1);
+        assert_eq!(verdict.respot, vec![YELLOW]);
+        assert_eq!(game.ball_on, BallOn::Colour);
+    }
+
+    #[test]
+    fn test_clearance_and_frame_end() {
+        let mut game = Game::new(0);
+        game.on_table = vec![PINK, BLACK];
+        game.ball_on = BallOn::Clearance(PINK);
+        game.scores = [30, 20];
+        game.play(None, &pot(PINK, &[PINK]));
+        assert_eq!(game.ball_on, BallOn::Clearance(BLACK));
+        assert!(!game.on_table.contains(&PINK));
+        game.play(None, &pot(BLACK, &[BLACK]));
+        assert_eq!(game.winner, Some(0));
+        assert_eq!(game.scores, [43, 20]);
+    }
+
+    #[test]
+    fn test_tie_respots_black() {
+        let mut game = Game::new(0);
+        game.on_table = vec![BLACK];
+        game.ball_on = BallOn::Clearance(BLACK);
+        game.scores = [30, 37];
+        game.play(None, &pot(BLACK, &[BLACK]));
+        assert_eq!(game.winner, None);
+        assert_eq!(game.on_table, vec![BLACK]);
+    }
+
+    #[test]
+    fn test_respot_occupied_spot() {
+        let r = consts::SNOOKER_BALL_RADIUS;
+        assert_eq!(respot_position(PINK, &[], r), spot_of(PINK));
+
+        // Own spot taken: the highest free spot is used.
+        let pos = respot_position(PINK, &[spot_of(PINK)], r);
+        assert_eq!(pos, spot_of(BLACK));
+
+        // All spots taken: as near as possible towards the top cushion.
+        let occupied: Vec<JVector3> = (YELLOW..BLACK+1).map(spot_of).collect();
+        let pos = respot_position(BLUE, &occupied, r);
+        assert!(pos.x > spot_of(BLUE).x);
+        assert!(pos.x - spot_of(BLUE).x < 2.2 * r);
+        assert_eq!(pos.y, 0.);
+    }
+
+    #[test]
+    fn test_is_snookered() {
+        let game = Game::new(0);
+        let r = consts::SNOOKER_BALL_RADIUS;
+        let cue = JVector3::new(0., 0., r);
+        let red = (FIRST_RED, JVector3::new(0.5, 0., r));
+        assert!(!game.is_snookered(&cue, &[red], r));
+
+        let blocker = (BLUE, JVector3::new(0.25, 0., r));
+        assert!(game.is_snookered(&cue, &[red, blocker], r));
+    }
+}
@@ -0,0 +1,252 @@
+// Three-cushion carom billiards. Player 0 plays the white ball and player 1
+// the yellow; the red is shared. A point is made when the cue ball contacts
+// both other balls and has touched the cushions at least three times before
+// it reaches the second one.
+
+use geometry::JVector3;
+use jlib::{Ball, WorldConf, consts};
+use rules::{Scene, ShotEvent, ShotOutcome, other_player};
+
+pub const WHITE: u32 = 0;
+pub const YELLOW: u32 = 1;
+pub const RED: u32 = 2;
+
+pub const MIN_CUSHIONS: u32 = 3;
+
+// The distance of the cue ball from the opponent's ball on the head string
+// at the opening shot.
+pub const OPENING_OFFSET: f64 = 0.152;
+
+pub fn cue_ball_of(player: usize) -> u32 {
+    if player == 0 { WHITE } else { YELLOW }
+}
+
+pub fn is_point(outcome: &ShotOutcome, cue: u32) -> bool {
+    let mut cushions = 0;
+    let mut first_object: Option<u32> = None;
+    for event in outcome.events.iter() {
+        match *event {
+            ShotEvent::Cushion(ball) if ball == cue => cushions += 1,
+            ShotEvent::BallBall(a, b) if a == cue || b == cue => {
+                let object = if a == cue { b } else { a };
+                match first_object {
+                    None => first_object = Some(object),
+                    Some(first) if first != object => return cushions >= MIN_CUSHIONS,
+                    Some(_) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+pub fn default_scene() -> Scene {
+    let r = consts::CAROM_BALL_RADIUS;
+    let world_conf = WorldConf::carom();
+
+    // Opening position: red on the foot spot, yellow on the head spot and
+    // white on the head string beside it.
+    let quarter = consts::CAROM_TABLE_LENGTH / 4.;
+    let balls = vec![
+        Ball::at_rest(JVector3::new(-quarter, -OPENING_OFFSET, r)),
+        Ball::at_rest(JVector3::new(-quarter, 0., r)),
+        Ball::at_rest(JVector3::new(quarter, 0., r)),
+    ];
+
+    Scene { world_conf: world_conf, balls: balls, numbers: vec![WHITE, YELLOW, RED] }
+}
+
+#[derive(Clone, Debug)]
+pub struct Match {
+    // Points needed to win.
+    pub target: u32,
+    // When the player who opened reaches the target, the opponent still gets
+    // an inning so that both have had the same number.
+    pub equal_innings: bool,
+    pub opener: usize,
+    pub shooter: usize,
+    pub scores: [u32; 2],
+    pub innings: [u32; 2],
+    pub current_run: u32,
+    pub high_runs: [u32; 2],
+    pub winner: Option<usize>,
+    // Set when the match has ended in a draw after equal innings.
+    pub is_draw: bool,
+}
+
+impl Match {
+    pub fn new(target: u32, opener: usize, equal_innings: bool) -> Self {
+        Match {
+            target: target,
+            equal_innings: equal_innings,
+            opener: opener,
+            shooter: opener,
+            scores: [0, 0],
+            innings: [0, 0],
+            current_run: 0,
+            high_runs: [0, 0],
+            winner: None,
+            is_draw: false,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner.is_some() || self.is_draw
+    }
+
+    // Returns whether the shot made a point.
+    pub fn play(&mut self, outcome: &ShotOutcome) -> bool {
+        if self.is_over() {
+            panic!("play called on a finished three-cushion match");
+        }
+
+        if self.current_run == 0 {
+            self.innings[self.shooter] += 1;
+        }
+
+        let point = is_point(outcome, cue_ball_of(self.shooter));
+        if point {
+            self.scores[self.shooter] += 1;
+            self.current_run += 1;
+            self.high_runs[self.shooter] = self.high_runs[self.shooter].max(self.current_run);
+            if self.scores[self.shooter] >= self.target {
+                self.end_inning();
+            }
+        }
+        else {
+            self.end_inning();
+        }
+        point
+    }
+
+    fn end_inning(&mut self) {
+        let shooter = self.shooter;
+        self.current_run = 0;
+        self.shooter = other_player(shooter);
+
+        let opponent = other_player(self.opener);
+        let (scores, target) = (self.scores, self.target);
+        let reached = |player: usize| scores[player] >= target;
+        if shooter == self.opener && reached(shooter) {
+            if !self.equal_innings {
+                self.winner = Some(shooter);
+            }
+        }
+        else if shooter == opponent && (reached(shooter) || reached(self.opener)) {
+            // The inning that evens the count is over.
+            if reached(shooter) && reached(self.opener) {
+                self.is_draw = true;
+            }
+            else {
+                self.winner = Some(if reached(shooter) { shooter } else { self.opener });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rules::{ShotEvent, ShotOutcome};
+    use rules::three_cushion::{Match, RED, WHITE, YELLOW, default_scene, is_point};
+
+    fn point_for(cue: u32) -> ShotOutcome {
+        let first = if cue == WHITE { YELLOW } else { WHITE };
+        ShotOutcome::new(vec![
+            ShotEvent::BallBall(cue, RED),
+            ShotEvent::Cushion(cue),
+            ShotEvent::Cushion(cue),
+            ShotEvent::Cushion(cue),
+            ShotEvent::BallBall(first, cue),
+        ])
+    }
+
+    fn miss() -> ShotOutcome {
+        ShotOutcome::new(vec![])
+    }
+
+    #[test]
+    fn test_default_scene_has_no_pockets() {
+        let scene = default_scene();
+        assert!(scene.world_conf.table.unwrap().pockets.is_empty());
+        assert_eq!(scene.balls.len(), 3);
+    }
+
+    #[test]
+    fn test_is_point() {
+        assert!(is_point(&point_for(WHITE), WHITE));
+
+        // Cushions after the second ball do not count.
+        assert!(!is_point(&ShotOutcome::new(vec![
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::BallBall(WHITE, RED),
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::BallBall(WHITE, YELLOW),
+            ShotEvent::Cushion(WHITE),
+        ]), WHITE));
+
+        // Object balls touching cushions do not count either.
+        assert!(!is_point(&ShotOutcome::new(vec![
+            ShotEvent::BallBall(WHITE, RED),
+            ShotEvent::Cushion(RED),
+            ShotEvent::Cushion(RED),
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::BallBall(WHITE, YELLOW),
+        ]), WHITE));
+
+        // Hitting the same ball twice is not enough.
+        assert!(!is_point(&ShotOutcome::new(vec![
+            ShotEvent::BallBall(WHITE, RED),
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::Cushion(WHITE),
+            ShotEvent::BallBall(RED, WHITE),
+        ]), WHITE));
+    }
+
+    #[test]
+    fn test_innings_and_runs() {
+        let mut game = Match::new(10, 0, false);
+        assert!(game.play(&point_for(WHITE)));
+        assert!(game.play(&point_for(WHITE)));
+        assert!(!game.play(&miss()));
+        assert_eq!(game.shooter, 1);
+        assert!(game.play(&point_for(YELLOW)));
+        assert!(!game.play(&miss()));
+
+        assert_eq!(game.scores, [2, 1]);
+        assert_eq!(game.innings, [1, 1]);
+        assert_eq!(game.high_runs, [2, 1]);
+    }
+
+    #[test]
+    fn test_match_win() {
+        let mut game = Match::new(2, 0, false);
+        game.play(&point_for(WHITE));
+        game.play(&point_for(WHITE));
+        assert_eq!(game.winner, Some(0));
+    }
+
+    #[test]
+    fn test_equal_innings() {
+        let mut game = Match::new(2, 0, true);
+        game.play(&point_for(WHITE));
+        game.play(&point_for(WHITE));
+        assert!(!game.is_over());
+        assert_eq!(game.shooter, 1);
+
+        game.play(&point_for(YELLOW));
+        game.play(&point_for(YELLOW));
+        assert!(game.is_draw);
+    }
+
+    #[test]
+    fn test_equal_innings_opponent_fails() {
+        let mut game = Match::new(2, 0, true);
+        game.play(&point_for(WHITE));
+        game.play(&point_for(WHITE));
+        game.play(&point_for(YELLOW));
+        game.play(&miss());
+        assert_eq!(game.winner, Some(0));
+    }
+}
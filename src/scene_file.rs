@@ -0,0 +1,187 @@
+// A plain text format for scenes. Every line holds one statement; blank lines
+// and everything after a # are ignored.
+//
+//     table snooker                  pool, snooker, carom or open
+//     cloth snooker_napped           one of the cloth::ClothPreset names
+//     set ball_cloth_friction 0.22   overrides a single parameter
+//     slope 0.001 0                  x and y of TableConditions::slope
+//     ball 0 -1.2 0.1                number, x and y of a ball on the cloth
+//
+// The table sets the balls and the defaults of every parameter, so it can
+// only be given first. Without it the table is a pool table. The statements
+// after it are applied in order, so a set after a cloth overrides the preset.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use cloth::ClothPreset;
+use geometry::JVector3;
+use jlib::{Ball, WorldConf, consts};
+use rules::Scene;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(io::Error),
+    // line counts from 1.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneFileError::Io(ref e) => write!(f, "cannot read scene file: {}", e),
+            SceneFileError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for SceneFileError {
+    fn from(e: io::Error) -> Self {
+        SceneFileError::Io(e)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneFileError> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse(&text)
+}
+
+fn world_conf_for(table: &str) -> Option<WorldConf> {
+    match table {
+        "pool" => Some(WorldConf::pool()),
+        "snooker" => Some(WorldConf::snooker()),
+        "carom" => Some(WorldConf::carom()),
+        "open" => Some(WorldConf::new(consts::POOL_BALL_RADIUS, consts::POOL_BALL_WEIGHT, None)),
+        _ => None,
+    }
+}
+
+// Returns false for an unknown name.
+fn set(world_conf: &mut WorldConf, name: &str, value: f64) -> bool {
+    match name {
+        "gravity" => world_conf.gravity = value,
+        "ball_ball_rest" => world_conf.ball_ball_rest = value,
+        "ball_cloth_rest" => world_conf.ball_cloth_rest = value,
+        "ball_cloth_friction" => world_conf.ball_cloth_friction = value,
+        "ball_cloth_rolling_resistance" => world_conf.ball_cloth_rolling_resistance = value,
+        "ball_cloth_spin_decay" => world_conf.ball_cloth_spin_decay = value,
+        "ball_cushion_rest" => match world_conf.table {
+            Some(ref mut table) => table.ball_cushion_rest = value,
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+pub fn parse(text: &str) -> Result<Scene, SceneFileError> {
+    let mut world_conf = world_conf_for("pool").unwrap();
+    let mut balls = Vec::new();
+    let mut numbers: Vec<u32> = Vec::new();
+    let mut is_first = true;
+
+    for (k, line) in text.lines().enumerate() {
+        let error = |message: String| SceneFileError::Parse { line: k + 1, message: message };
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        let number = |i: usize| -> Result<f64, SceneFileError> {
+            words[i].parse().map_err(|_| error(format!("{} is not a number", words[i])))
+        };
+        let arity = match words[0] {
+            "table" | "cloth" => 1,
+            "set" | "slope" => 2,
+            "ball" => 3,
+            other => return Err(error(format!("unknown statement {}", other))),
+        };
+        if words.len() != arity + 1 {
+            return Err(error(format!("{} takes {} arguments", words[0], arity)));
+        }
+
+        match words[0] {
+            "table" => {
+                if !is_first {
+                    return Err(error("table has to come first".to_string()));
+                }
+                world_conf = world_conf_for(words[1])
+                    .ok_or_else(|| error(format!("unknown table {}", words[1])))?;
+            }
+            "cloth" => {
+                let preset = ClothPreset::from_name(words[1])
+                    .ok_or_else(|| error(format!("unknown cloth {}", words[1])))?;
+                preset.apply(&mut world_conf);
+            }
+            "set" => {
+                let value = number(2)?;
+                if !set(&mut world_conf, words[1], value) {
+                    return Err(error(format!("cannot set {}", words[1])));
+                }
+            }
+            "slope" => world_conf.conditions.slope = JVector3::new(number(1)?, number(2)?, 0.),
+            _ => {
+                let ball_number: u32 = words[1].parse()
+                    .map_err(|_| error(format!("{} is not a ball number", words[1])))?;
+                if numbers.contains(&ball_number) {
+                    return Err(error(format!("ball {} is given twice", ball_number)));
+                }
+                let pos = JVector3::new(number(2)?, number(3)?, world_conf.ball_radius);
+                balls.push(Ball::at_rest(pos));
+                numbers.push(ball_number);
+            }
+        }
+        is_first = false;
+    }
+
+    Ok(Scene { world_conf: world_conf, balls: balls, numbers: numbers })
+}
+
+#[cfg(test)]
+mod tests {
+    use jlib::consts;
+    use scene_file::{SceneFileError, parse};
+
+    #[test]
+    fn test_parse() {
+        let scene = parse("
+            # Cut to the corner on worn cloth.
+            table snooker
+            cloth worn
+            set ball_cloth_friction 0.22   # measured
+            slope 0.001 0
+            ball 0 -1.2 0.1
+            ball 7 0.5 0
+        ").unwrap();
+
+        let world_conf = &scene.world_conf;
+        assert_eq!(world_conf.ball_radius, consts::SNOOKER_BALL_RADIUS);
+        assert_eq!(world_conf.ball_cloth_friction, 0.22);
+        assert_eq!(world_conf.ball_cloth_rolling_resistance, 0.012);
+        assert_eq!(world_conf.conditions.slope.x, 0.001);
+        assert_eq!(scene.numbers, vec![0, 7]);
+        assert_eq!(scene.balls[0].pos.x, -1.2);
+        assert_eq!(scene.balls[0].pos.z, consts::SNOOKER_BALL_RADIUS);
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        let line_of = |text: &str| match parse(text) {
+            Err(SceneFileError::Parse { line, .. }) => line,
+            _ => panic!("no parse error for {:?}", text),
+        };
+        assert_eq!(line_of("ball 0 0 0\ntable pool"), 2);
+        assert_eq!(line_of("cloth velvet"), 1);
+        assert_eq!(line_of("\nball 0 0 zero"), 2);
+        assert_eq!(line_of("ball 1 0 0\nball 1 0.5 0"), 2);
+        assert_eq!(line_of("table open\nset ball_cushion_rest 0.7"), 2);
+        assert_eq!(line_of("set ball_radius 0.03"), 1);
+    }
+}
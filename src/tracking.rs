@@ -0,0 +1,221 @@
+// Ball positions measured on a real table by a video tracker, for comparison
+// with simulations. The tracker exports CSV with one observation per line and
+// the columns time, ball, x and y, in seconds and metres, in the coordinates
+// of the simulation. A first line that does not start with a number is taken
+// to be a header.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use calibration::Observation;
+use jlib::SimulationStateSeq;
+
+#[derive(Debug)]
+pub enum TrackingError {
+    Io(io::Error),
+    // line counts from 1.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for TrackingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrackingError::Io(ref e) => write!(f, "cannot read tracking data: {}", e),
+            TrackingError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for TrackingError {
+    fn from(e: io::Error) -> Self {
+        TrackingError::Io(e)
+    }
+}
+
+pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Vec<Observation>, TrackingError> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse_csv(&text)
+}
+
+pub fn parse_csv(text: &str) -> Result<Vec<Observation>, TrackingError> {
+    let mut observations = Vec::new();
+    let mut is_first = true;
+    for (k, line) in text.lines().enumerate() {
+        let error = |message: String| TrackingError::Parse { line: k + 1, message: message };
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() == 1 && fields[0].is_empty() {
+            continue;
+        }
+        if is_first {
+            is_first = false;
+            if fields[0].parse::<f64>().is_err() {
+                continue;
+            }
+        }
+
+        if fields.len() != 4 {
+            return Err(error(format!("expected 4 fields, found {}", fields.len())));
+        }
+        // f64 parses NaN and inf too, which no tracker measures.
+        let number = |i: usize| -> Result<f64, TrackingError> {
+            match fields[i].parse::<f64>() {
+                Ok(value) if value.is_finite() => Ok(value),
+                Ok(_) => Err(error(format!("{} is not a finite number", fields[i]))),
+                Err(_) => Err(error(format!("{} is not a number", fields[i]))),
+            }
+        };
+        observations.push(Observation {
+            t: number(0)?,
+            ball: fields[1].parse().map_err(|_| error(format!("{} is not a ball number", fields[1])))?,
+            x: number(2)?,
+            y: number(3)?,
+        });
+    }
+    Ok(observations)
+}
+
+// The observations of one ball, in time order.
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub ball: u32,
+    pub observations: Vec<Observation>,
+}
+
+impl Track {
+    // Linearly interpolated between observations. None outside the time span
+    // of the track.
+    pub fn position_at(&self, t: f64) -> Option<(f64, f64)> {
+        let k = self.observations.iter().position(|o| o.t >= t)?;
+        let b = &self.observations[k];
+        if k == 0 {
+            return if b.t == t { Some((b.x, b.y)) } else { None };
+        }
+        let a = &self.observations[k - 1];
+        let w = (t - a.t) / (b.t - a.t);
+        Some((a.x + w * (b.x - a.x), a.y + w * (b.y - a.y)))
+    }
+}
+
+// Tracks in the order of the ball numbers.
+pub fn tracks(observations: &[Observation]) -> Vec<Track> {
+    let mut tracks: Vec<Track> = Vec::new();
+    for observation in observations.iter() {
+        match tracks.iter().position(|track| track.ball == observation.ball) {
+            Some(k) => tracks[k].observations.push(observation.clone()),
+            None => tracks.push(Track { ball: observation.ball, observations: vec![observation.clone()] }),
+        }
+    }
+    for track in tracks.iter_mut() {
+        track.observations.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+    }
+    tracks.sort_by_key(|track| track.ball);
+    tracks
+}
+
+// How far the simulated ball was from the measured one over time.
+#[derive(Clone, Debug)]
+pub struct Deviation {
+    pub ball: u32,
+    // (t, distance) at the time of every observation of the ball.
+    pub series: Vec<(f64, f64)>,
+    pub mean: f64,
+    pub rms: f64,
+    pub max: f64,
+}
+
+// numbers[i] is the number of the ball at index i of the simulated states.
+// Tracks of balls that are not in numbers are skipped.
+pub fn deviations(tracks: &[Track], states: &SimulationStateSeq, numbers: &[u32]) -> Vec<Deviation> {
+    tracks.iter().filter_map(|track| {
+        let i = numbers.iter().position(|&number| number == track.ball)?;
+        let series: Vec<(f64, f64)> = track.observations.iter().map(|o| {
+            let pos = states.calc_interpolated_at(o.t).balls[i].pos;
+            (o.t, ((pos.x - o.x).powi(2) + (pos.y - o.y).powi(2)).sqrt())
+        }).collect();
+        let n = series.len() as f64;
+        Some(Deviation {
+            ball: track.ball,
+            mean: series.iter().map(|&(_, d)| d).sum::<f64>() / n,
+            rms: (series.iter().map(|&(_, d)| d * d).sum::<f64>() / n).sqrt(),
+            max: series.iter().map(|&(_, d)| d).fold(0., f64::max),
+            series: series,
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use calibration::Observation;
+    use geometry::JVector3;
+    use jlib::{Shot, SimulationStateSeq};
+    use scene_file;
+    use tracking::{TrackingError, deviations, parse_csv, tracks};
+
+    #[test]
+    fn test_parse_csv() {
+        let observations = parse_csv("time,ball,x,y\n0.0, 3, 0.1, -0.2\n\n0.5,0,1,2\n").unwrap();
+        assert_eq!(observations, vec![
+            Observation { t: 0., ball: 3, x: 0.1, y: -0.2 },
+            Observation { t: 0.5, ball: 0, x: 1., y: 2. },
+        ]);
+        assert_eq!(parse_csv("0.0,3,0.1,-0.2").unwrap().len(), 1);
+
+        match parse_csv("t,ball,x,y\n0,1,2,3\n0,1,2") {
+            Err(TrackingError::Parse { line: 3, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(parse_csv("0,1.5,2,3").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_rejects_non_finite_numbers() {
+        let texts = [("t,ball,x,y\n0,1,2,3\nNaN,1,2,3", 3), ("0,1,2,3\n0.1,1,inf,3", 2), ("0,1,2,3\n0.1,1,2,-inf", 2)];
+        for &(text, line) in texts.iter() {
+            match parse_csv(text) {
+                Err(TrackingError::Parse { line: l, .. }) if l == line => {}
+                other => panic!("unexpected result {:?} for {:?}", other, text),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tracks_and_interpolation() {
+        let tracks = tracks(&parse_csv("1,2,1,1\n0,2,0,0\n0,1,5,5").unwrap());
+        assert_eq!(tracks.iter().map(|track| track.ball).collect::<Vec<u32>>(), vec![1, 2]);
+        assert_eq!(tracks[1].position_at(0.25), Some((0.25, 0.25)));
+        assert_eq!(tracks[1].position_at(0.), Some((0., 0.)));
+        assert_eq!(tracks[1].position_at(1.5), None);
+        assert_eq!(tracks[1].position_at(-0.5), None);
+    }
+
+    #[test]
+    fn test_deviations() {
+        let (mut simulator, numbers) = scene_file::parse("table open\nball 0 0 0\nball 4 0.5 0")
+            .unwrap()
+            .into_simulator(1e-3);
+        simulator.apply_shot(&Shot { ball: 0, u: JVector3::new(0., 1., 0.), omega: JVector3::zeros() });
+        let mut states = SimulationStateSeq { states: vec![] };
+        let mut observations = Vec::new();
+        for k in 0..1000 {
+            let state = simulator.progress();
+            if k % 100 == 0 {
+                // Measured 1 cm to the side of the simulated path.
+                let pos = state.balls[0].pos;
+                observations.push(Observation { t: state.t, ball: 0, x: pos.x + 0.01, y: pos.y });
+            }
+            states.states.push(state);
+        }
+        observations.push(Observation { t: 0.5, ball: 9, x: 0., y: 0. });
+
+        let deviations = deviations(&tracks(&observations), &states, &numbers);
+        assert_eq!(deviations.len(), 1);
+        let deviation = &deviations[0];
+        assert_eq!(deviation.series.len(), 10);
+        assert!((deviation.mean - 0.01).abs() < 1e-9);
+        assert!((deviation.rms - 0.01).abs() < 1e-9);
+        assert!((deviation.max - 0.01).abs() < 1e-9);
+    }
+}